@@ -2,26 +2,36 @@ use anyhow::{Context, Result};
 use clap::Parser;
 use serde::{Deserialize, Serialize};
 use std::{
-    collections::HashMap,
+    collections::{HashMap, VecDeque},
     fs::{File, OpenOptions},
-    io::Read,
-    os::fd::IntoRawFd,
-    path::PathBuf,
-    process::{Command, Stdio},
+    io::{BufRead, BufReader, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    os::{
+        fd::{AsRawFd, IntoRawFd},
+        unix::net::{UnixListener, UnixStream},
+    },
+    path::{Path, PathBuf},
+    os::unix::process::{CommandExt, ExitStatusExt},
+    process::{Child, Command, ExitStatus, Stdio},
     sync::{
-        atomic::{AtomicBool, Ordering},
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
         Arc, Mutex,
     },
     thread,
-    time::Duration,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 // Import specific items from libc instead of the entire module
 use libc::{close, dup2, fork, setsid, STDERR_FILENO, STDIN_FILENO, STDOUT_FILENO};
 
 macro_rules! pactl {
-    ($($args:expr),*) => {
-        run_command("pactl", &[$($args),*])
-    };
+    ($($args:expr),*) => {{
+        let args: &[&str] = &[$($args),*];
+        // Calls that change state go through the rate limiter
+        if args[0].starts_with("set-") {
+            throttle_pactl();
+        }
+        run_command("pactl", args)
+    }};
 }
 
 // Define notification states for state tracking
@@ -31,18 +41,892 @@ enum NotificationState {
     Playing,
     FadingIn,
     Idle,
+    // Shutting down: takes no more requests, a new server starts once it is gone
+    Exiting,
 }
 
 // Common constant for fade steps
 const FADE_STEPS: u8 = 10;
 
+// Default interval (seconds) for polling the lock file and the playback monitor
+const DEFAULT_POLL_INTERVAL: f32 = 0.05;
+// Longest poll interval, and longest --max-duration and --idle-timeout (a week), in seconds
+const MAX_POLL_INTERVAL: f32 = 10.0;
+const MAX_WAIT_SETTING: f32 = 7.0 * 24.0 * 3600.0;
+
+// Safety timeout (seconds) after which the playback monitor stops watching for interruptions,
+// for sounds whose length is unknown; by default a sound of known length is watched throughout
+const DEFAULT_MAX_DURATION: f32 = 10.0;
+// State-changing pactl calls per second; fades step far slower than this. Below the minimum a
+// single fade would take minutes
+const DEFAULT_PACTL_RATE: f32 = 100.0;
+const MIN_PACTL_RATE: f32 = 0.1;
+
+// Events broadcast to subscribers of the control socket, one JSON object per line
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum Event {
+    FadeOut { from: f32, to: f32, ms: u64 },
+    Playing {
+        sound: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        alias: Option<String>,
+    },
+    Failed { sound: String, error: String },
+    Interrupted,
+    FadeIn { from: f32, to: f32, ms: u64 },
+    Idle,
+    Reloaded,
+    Acknowledged { sound: String },
+}
+
+// Requests accepted on the control socket, one JSON object per line
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+enum ControlRequest {
+    Subscribe,
+    // Drop queued notifications and any pending lock-file request;
+    // with `now` the currently playing sound is stopped as well
+    Flush { now: bool },
+    // Queue a sound; unlike the lock file, concurrent requests can't overwrite each other
+    Play {
+        sound: String,
+        #[serde(default)]
+        alias: Option<String>,
+        #[serde(default)]
+        options: RequestOptions,
+        #[serde(default)]
+        pid: Option<u32>,
+        #[serde(default)]
+        once: Option<String>,
+    },
+    // Report the playing sound and the queued requests
+    List,
+    // Drop the queued request with this id, or stop it if it is playing
+    Cancel { id: u64 },
+    // Reload the config before the next sound, like SIGHUP
+    Reload,
+    // Stop repeating the --until-ack sound
+    Ack,
+    // Fade back in audio left ducked by --no-restore
+    Restore,
+}
+
+// Reply sent for control requests that expect one
+#[derive(Debug, Serialize, Deserialize)]
+struct ControlResponse {
+    ok: bool,
+    message: String,
+    // Reply to List
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    queue: Option<QueueListing>,
+}
+
+// The live queue as reported to --list-queue and GUIs
+#[derive(Debug, Serialize, Deserialize)]
+struct QueueListing {
+    playing: Option<QueueEntry>,
+    queued: Vec<QueueEntry>,
+    // The --until-ack sound that repeats until acknowledged
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    awaiting_ack: Option<QueueEntry>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct QueueEntry {
+    id: u64,
+    sound: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    alias: Option<String>,
+    priority: Priority,
+    #[serde(default)]
+    urgent: bool,
+    // Unix time in seconds at which the request was queued
+    queued_at: f64,
+    // Seconds played so far and left to play; only for the playing sound, and the
+    // remaining time only when its length could be measured (WAV header, ffprobe or soxi)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    elapsed: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    remaining: Option<f32>,
+}
+
+impl QueueEntry {
+    fn new(sound: &QueuedSound) -> Self {
+        let queued_at = SystemTime::now()
+            .checked_sub(sound.queued_at.elapsed())
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map(|since| since.as_secs_f64())
+            .unwrap_or_default();
+        QueueEntry {
+            id: sound.id,
+            sound: sound.path.to_string_lossy().to_string(),
+            alias: sound.alias.clone(),
+            priority: sound.options.priority,
+            urgent: sound.options.urgent,
+            queued_at,
+            elapsed: None,
+            remaining: None,
+        }
+    }
+}
+
+// The sound being played, for List and Cancel
+struct PlayingSound {
+    sound: QueuedSound,
+    started: Instant,
+    duration: Option<Duration>,
+}
+
+// Request sent to a server listening on TCP (--listen); the sound is resolved by the server
+#[derive(Debug, Serialize, Deserialize)]
+struct RemoteRequest {
+    token: String,
+    sound: String,
+    #[serde(default)]
+    options: RequestOptions,
+    #[serde(default)]
+    once: Option<String>,
+}
+
+// State needed to accept requests from remote clients
+struct RemoteListener {
+    settings: Mutex<RemoteSettings>,
+    foreground: bool,
+    control: Arc<ControlState>,
+}
+
+// What remote requests are checked and resolved against; replaced when the config is reloaded
+#[derive(Clone)]
+struct RemoteSettings {
+    token: String,
+    config: Config,
+    volume: f32,
+}
+
+// Server state reachable from the control socket. Locks are taken in this order: the queue,
+// then current, attention and the rest, so anything that changes what --persist-queue saves
+// can save it with the queue locked
+struct ControlState {
+    events: EventBus,
+    notification_queue: Arc<Mutex<VecDeque<QueuedSound>>>,
+    // The request picked from the queue, from then until it has played
+    current: Mutex<Option<QueuedSound>>,
+    // --once keys accepted, with the time each one is forgotten (if ever)
+    once_keys: Mutex<HashMap<String, Option<Instant>>>,
+    // The longest queue kept and what happens beyond that; unbounded when unset
+    queue_limit: Mutex<Option<QueueLimit>>,
+    // Where the queue is saved with --persist-queue; unset otherwise
+    queue_file: Mutex<Option<PathBuf>>,
+    // Set to stop the sound that is currently playing without queuing another
+    skip_current: AtomicBool,
+    playing: Mutex<Option<PlayingSound>>,
+    lock_path: PathBuf,
+    // Set once the server has decided to exit; Play requests are then turned away
+    exiting: AtomicBool,
+    // The --until-ack sound, played again whenever nothing else is queued until acknowledged
+    attention: Mutex<Option<QueuedSound>>,
+    // Audio stays ducked after a --no-restore sound until this time (or a --restore)
+    hold_until: Mutex<Option<Instant>>,
+}
+
+// Connected event subscribers; streams that fail to accept a write are dropped
+#[derive(Default)]
+struct EventBus {
+    subscribers: Mutex<Vec<UnixStream>>,
+    // Also print every event to stdout (foreground mode)
+    log: bool,
+    // Also post every event to --webhook
+    #[cfg(feature = "webhook")]
+    webhook: Option<Webhook>,
+}
+
+impl EventBus {
+    fn subscribe(&self, stream: UnixStream) {
+        // Never let a slow subscriber stall the server
+        let _ = stream.set_write_timeout(Some(Duration::from_millis(100)));
+        self.subscribers.lock().unwrap().push(stream);
+    }
+
+    fn emit(&self, event: &Event) {
+        let mut subscribers = self.subscribers.lock().unwrap();
+        #[cfg(feature = "webhook")]
+        let posting = self.webhook.is_some();
+        #[cfg(not(feature = "webhook"))]
+        let posting = false;
+        if subscribers.is_empty() && !self.log && !posting {
+            return;
+        }
+        let Ok(mut line) = serde_json::to_string(event) else {
+            return;
+        };
+        if self.log {
+            println!("event: {}", line);
+        }
+        #[cfg(feature = "webhook")]
+        if let Some(webhook) = &self.webhook {
+            webhook.post(line.clone());
+        }
+        line.push('\n');
+        subscribers.retain_mut(|stream| stream.write_all(line.as_bytes()).is_ok());
+    }
+}
+
+// A --webhook target: a plain http:// URL split into what the request needs
+#[derive(Debug, Clone)]
+#[cfg_attr(not(feature = "webhook"), allow(dead_code))]
+struct WebhookUrl {
+    // host[:port] as sent in the Host header
+    authority: String,
+    // host:port to connect to
+    addr: String,
+    path: String,
+}
+
+fn parse_webhook_url(url: &str) -> Result<WebhookUrl> {
+    if !cfg!(feature = "webhook") {
+        anyhow::bail!("--webhook is not available: this build was made without the webhook feature");
+    }
+    let rest = url
+        .strip_prefix("http://")
+        .context(format!("Unsupported webhook URL {} (only http:// URLs are supported)", url))?;
+    let (authority, path) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+    if authority.is_empty() {
+        anyhow::bail!("Webhook URL {} has no host", url);
+    }
+    // The colon of an IPv6 address ([::1]) is not a port separator
+    let addr = match authority.rsplit_once(':').filter(|(_, port)| !port.contains(']')) {
+        Some((_, port)) => {
+            port.parse::<u16>().context(format!("Invalid port in webhook URL {}", url))?;
+            authority.to_string()
+        }
+        None => format!("{}:80", authority),
+    };
+    Ok(WebhookUrl {
+        authority: authority.to_string(),
+        addr,
+        path: if path.is_empty() { "/".to_string() } else { path.to_string() },
+    })
+}
+
+// Posts events from a thread of its own, in order, so a slow or unreachable endpoint never
+// holds up playback; an event that can't be delivered is logged and dropped
+#[cfg(feature = "webhook")]
+struct Webhook {
+    sender: std::sync::mpsc::Sender<String>,
+    // Events handed over but not yet posted (or given up on)
+    pending: Arc<AtomicUsize>,
+}
+
+#[cfg(feature = "webhook")]
+impl Webhook {
+    fn start(url: WebhookUrl) -> Self {
+        let (sender, receiver) = std::sync::mpsc::channel::<String>();
+        let pending = Arc::new(AtomicUsize::new(0));
+        let pending_clone = pending.clone();
+        thread::spawn(move || {
+            for body in receiver {
+                if let Err(e) = post_json(&url, &body) {
+                    eprintln!("Warning: Failed to post event to webhook http://{}{}: {:#}", url.authority, url.path, e);
+                }
+                pending_clone.fetch_sub(1, Ordering::SeqCst);
+            }
+        });
+        Self { sender, pending }
+    }
+
+    fn post(&self, body: String) {
+        self.pending.fetch_add(1, Ordering::SeqCst);
+        if self.sender.send(body).is_err() {
+            self.pending.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+
+    // Give the last events (e.g. the final idle) a chance to go out before the server exits
+    fn wait_sent(&self, timeout: Duration) {
+        let started = Instant::now();
+        while self.pending.load(Ordering::SeqCst) > 0 && started.elapsed() < timeout {
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+}
+
+// A minimal HTTP/1.1 POST; any 2xx status counts as delivered
+#[cfg(feature = "webhook")]
+fn post_json(url: &WebhookUrl, body: &str) -> Result<()> {
+    let addr = url
+        .addr
+        .to_socket_addrs()
+        .context(format!("Failed to resolve {}", url.addr))?
+        .next()
+        .context(format!("No address found for {}", url.addr))?;
+    let mut stream = TcpStream::connect_timeout(&addr, WEBHOOK_TIMEOUT).context(format!("Failed to connect to {}", url.addr))?;
+    stream.set_read_timeout(Some(WEBHOOK_TIMEOUT))?;
+    stream.set_write_timeout(Some(WEBHOOK_TIMEOUT))?;
+    write!(
+        stream,
+        "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        url.path,
+        url.authority,
+        body.len(),
+        body
+    )?;
+
+    let mut status_line = String::new();
+    BufReader::new(&stream).read_line(&mut status_line)?;
+    let status = status_line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|code| code.parse::<u16>().ok())
+        .context(format!("Invalid HTTP response: {}", status_line.trim()))?;
+    if !(200..300).contains(&status) {
+        anyhow::bail!("HTTP status {}", status);
+    }
+    Ok(())
+}
+
+// How a request competes with the sound that is playing when it arrives
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+enum Priority {
+    Low,
+    #[default]
+    Normal,
+    High,
+}
+
+// Per-request playback options sent along with the sound path
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+struct RequestOptions {
+    #[serde(default)]
+    priority: Priority,
+    // Notification volume for this sound instead of the server's
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    volume: Option<f32>,
+    // Notification volume as a percentage of the sink volume before ducking; wins over volume
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    relative_volume: Option<f32>,
+    // Never interrupted by other requests while playing
+    #[serde(default)]
+    urgent: bool,
+    // Seconds a queued request stays relevant; expired requests are dropped unplayed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    ttl: Option<f32>,
+    // Flash the screen backlight while the sound plays
+    #[serde(default)]
+    flash: bool,
+    // Flash instead of playing the sound
+    #[serde(default)]
+    silent: bool,
+    // When picking from a directory, avoid the file picked last time
+    #[serde(default)]
+    no_repeat: bool,
+    // Seconds the server remembers the request's --once key; for its lifetime when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    once_for: Option<f32>,
+    // Repeat with the audio kept ducked until acknowledged with --ack
+    #[serde(default)]
+    until_ack: bool,
+    // Leave the audio ducked afterwards for the next sound, up to NO_RESTORE_TIMEOUT
+    #[serde(default)]
+    no_restore: bool,
+}
+
+impl RequestOptions {
+    // Refuse values from clients (socket, lock file, remote, saved queue) that the server can't act on
+    fn validate(&self) -> Result<()> {
+        if let Some(secs) = self.once_for {
+            if !secs.is_finite() || !(0.0..=MAX_ONCE_FOR).contains(&secs) {
+                anyhow::bail!("Invalid once_for {} (expected 0 to {} seconds)", secs, MAX_ONCE_FOR);
+            }
+        }
+        Ok(())
+    }
+
+    // A playing sound is cut off by a queued request of the same or higher priority
+    // (strictly higher when the policy keeps every request), unless it is urgent;
+    // other requests wait until it has finished
+    fn interrupted_by(&self, queued: &RequestOptions, policy: QueuePolicy) -> bool {
+        if self.urgent {
+            return false;
+        }
+        match policy {
+            QueuePolicy::Latest => queued.priority >= self.priority,
+            QueuePolicy::Fifo | QueuePolicy::Lifo => queued.priority > self.priority,
+        }
+    }
+}
+
+// Which queued request plays next when several are waiting
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+enum QueuePolicy {
+    // Play the newest request and drop the rest
+    #[default]
+    Latest,
+    // Play every request, oldest first
+    Fifo,
+    // Play every request, newest first
+    Lifo,
+}
+
+// What happens to a request arriving when the queue already holds --max-queue requests
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+enum OverflowPolicy {
+    // Drop the oldest waiting request of the lowest priority to make room; a request of lower
+    // priority than every waiting one is dropped instead
+    #[default]
+    DropOldest,
+    // Drop the arriving request
+    DropNewest,
+    // Drop every waiting request along with the arriving one and queue the overflow sound instead
+    Sound,
+}
+
+// What plays sounds that belong to an XDG sound theme
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+enum BackendPlayer {
+    // Play the theme file like any other sound
+    #[default]
+    Paplay,
+    // Hand the event id to canberra-gtk-play, which picks the file from the user's theme
+    Canberra,
+}
+
+// Ids handed to queued sounds, unique for the lifetime of the server
+static NEXT_REQUEST_ID: AtomicU64 = AtomicU64::new(1);
+
+// A sound waiting in the notification server's queue
+#[derive(Debug, Clone)]
+struct QueuedSound {
+    id: u64,
+    path: PathBuf,
+    // Alias or path as the client gave it
+    alias: Option<String>,
+    options: RequestOptions,
+    // Dedupe key: the server drops requests whose key it has already accepted
+    once: Option<String>,
+    queued_at: Instant,
+}
+
+impl QueuedSound {
+    fn new(path: PathBuf, alias: Option<String>, options: RequestOptions) -> Self {
+        QueuedSound {
+            id: NEXT_REQUEST_ID.fetch_add(1, Ordering::Relaxed),
+            path,
+            alias,
+            options,
+            once: None,
+            queued_at: Instant::now(),
+        }
+    }
+
+    fn with_once(mut self, once: Option<String>) -> Self {
+        self.once = once;
+        self
+    }
+
+    fn is_expired(&self) -> bool {
+        self.options.ttl.is_some_and(|ttl| self.queued_at.elapsed().as_secs_f32() > ttl)
+    }
+}
+
+// Repeated plays for --every: when the next one is due and how many are left (None: until stopped)
+#[derive(Debug, Clone, Copy)]
+struct PlaySchedule {
+    every: Duration,
+    next: Instant,
+    remaining: Option<u32>,
+}
+
+impl PlaySchedule {
+    // The first play happens right away and counts towards `count`. A next play later than
+    // an Instant can hold never comes
+    fn new(every: Duration, count: Option<u32>) -> Self {
+        let now = Instant::now();
+        match now.checked_add(every) {
+            Some(next) => PlaySchedule {
+                every,
+                next,
+                remaining: count.map(|count| count.saturating_sub(1)),
+            },
+            None => PlaySchedule {
+                every,
+                next: now,
+                remaining: Some(0),
+            },
+        }
+    }
+
+    fn done(&self) -> bool {
+        self.remaining == Some(0)
+    }
+
+    /// Sleep until the next play is due; false when no plays are left or `running` was cleared
+    fn wait(&mut self, running: &AtomicBool, poll_interval: Duration) -> bool {
+        if self.done() {
+            return false;
+        }
+        loop {
+            if !running.load(Ordering::SeqCst) {
+                return false;
+            }
+            let now = Instant::now();
+            if now >= self.next {
+                break;
+            }
+            thread::sleep(poll_interval.min(self.next - now));
+        }
+
+        // Periods missed while the play was late are skipped rather than played back to back
+        while self.next <= Instant::now() {
+            match self.next.checked_add(self.every) {
+                Some(next) => self.next = next,
+                // This play is the last
+                None => {
+                    self.remaining = Some(1);
+                    break;
+                }
+            }
+        }
+        if let Some(remaining) = &mut self.remaining {
+            *remaining -= 1;
+        }
+        true
+    }
+}
+
+// Whether any queued request is still worth playing
+fn has_pending(queue: &VecDeque<QueuedSound>) -> bool {
+    queue.iter().any(|queued| !queued.is_expired())
+}
+
+// What became of a request handed to enqueue
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Enqueued {
+    Queued,
+    // Its --once key was already accepted
+    Duplicate,
+    // The queue was full and the overflow policy turned it away
+    Overflow,
+}
+
+// The longest queue the server keeps and what it does beyond that
+#[derive(Debug, Clone)]
+struct QueueLimit {
+    max: usize,
+    overflow: OverflowPolicy,
+    // Queued in place of the flood with OverflowPolicy::Sound
+    sound: Option<PathBuf>,
+}
+
+// Queue a request unless its --once key was already accepted, applying the overflow policy
+// when the queue is full. The key is only recorded once the request is queued, and the queue
+// stays locked until then, so a concurrent request with the same key is still a duplicate.
+fn enqueue(control: &ControlState, sound: QueuedSound) -> Enqueued {
+    let mut queue = control.notification_queue.lock().unwrap();
+    let now = Instant::now();
+    let once = {
+        let mut keys = control.once_keys.lock().unwrap();
+        keys.retain(|_, forget_at| forget_at.is_none_or(|at| at > now));
+        if sound.once.as_ref().is_some_and(|key| keys.contains_key(key)) {
+            return Enqueued::Duplicate;
+        }
+        // Requests are validated before they get here; a time that still can't be represented
+        // keeps the key for the server's lifetime rather than panicking with the keys locked
+        let forget_at = sound
+            .options
+            .once_for
+            .and_then(|secs| Duration::try_from_secs_f32(secs).ok())
+            .and_then(|once_for| now.checked_add(once_for));
+        sound.once.clone().map(|key| (key, forget_at))
+    };
+    let limit = control.queue_limit.lock().unwrap().clone().filter(|limit| limit.max > 0 && queue.len() >= limit.max);
+    let enqueued = match limit {
+        None => {
+            queue.push_back(sound);
+            Enqueued::Queued
+        }
+        Some(limit) => {
+            let (enqueued, dropped) = overflow_queue(&mut queue, sound, &limit);
+            forget_once_keys(control, &dropped);
+            enqueued
+        }
+    };
+    if let (Enqueued::Queued, Some((key, forget_at))) = (enqueued, once) {
+        control.once_keys.lock().unwrap().insert(key, forget_at);
+    }
+    save_queue(control, &queue);
+    enqueued
+}
+
+// Forget the --once keys of requests dropped without playing, so they can be sent again
+fn forget_once_keys<'a>(control: &ControlState, dropped: impl IntoIterator<Item = &'a QueuedSound>) {
+    let mut keys = control.once_keys.lock().unwrap();
+    for key in dropped.into_iter().filter_map(|sound| sound.once.as_ref()) {
+        keys.remove(key);
+    }
+}
+
+// Make room in (or refuse) a full queue as its overflow policy says, logging what is dropped;
+// returns the waiting requests it dropped along with what became of the new one
+fn overflow_queue(queue: &mut VecDeque<QueuedSound>, sound: QueuedSound, limit: &QueueLimit) -> (Enqueued, Vec<QueuedSound>) {
+    match limit.overflow {
+        OverflowPolicy::DropOldest => {
+            // Room is made at the lowest priority waiting, and never for a request below all of them
+            let lowest = queue.iter().map(|queued| queued.options.priority).min();
+            if lowest.is_some_and(|lowest| sound.options.priority < lowest) {
+                eprintln!(
+                    "Warning: Queue full ({} requests of higher priority), dropped {}",
+                    limit.max,
+                    sound.path.display()
+                );
+                return (Enqueued::Overflow, Vec::new());
+            }
+            let oldest = queue.iter().position(|queued| Some(queued.options.priority) == lowest);
+            let dropped: Vec<QueuedSound> = oldest.and_then(|index| queue.remove(index)).into_iter().collect();
+            for oldest in &dropped {
+                eprintln!("Warning: Queue full ({} requests), dropped the oldest: {}", limit.max, oldest.path.display());
+            }
+            queue.push_back(sound);
+            (Enqueued::Queued, dropped)
+        }
+        OverflowPolicy::DropNewest => {
+            eprintln!("Warning: Queue full ({} requests), dropped {}", limit.max, sound.path.display());
+            (Enqueued::Overflow, Vec::new())
+        }
+        OverflowPolicy::Sound => {
+            eprintln!(
+                "Warning: Queue full ({} requests), dropped {} and every waiting request",
+                limit.max,
+                sound.path.display()
+            );
+            let dropped = queue.drain(..).collect();
+            if let Some(path) = &limit.sound {
+                let options = RequestOptions {
+                    priority: Priority::High,
+                    ..RequestOptions::default()
+                };
+                queue.push_back(QueuedSound::new(path.clone(), None, options));
+            }
+            (Enqueued::Overflow, dropped)
+        }
+    }
+}
+
+// The saved queue lives next to the lock file, so a server restarted after a crash finds it
+fn queue_file_path(lock_path: &Path) -> PathBuf {
+    lock_path.with_extension("queue.json")
+}
+
+// A waiting request as --persist-queue saves it, with the wall-clock time it was queued
+// so its TTL keeps counting while no server runs
+#[derive(Debug, Serialize, Deserialize)]
+struct SavedSound {
+    path: PathBuf,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    alias: Option<String>,
+    #[serde(default)]
+    options: RequestOptions,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    once: Option<String>,
+    // Seconds since the UNIX epoch
+    queued_at: f64,
+}
+
+// Save the unfinished requests after each change: the one playing, the waiting ones and the
+// --until-ack alert until it is acknowledged. The file goes away once there are none.
+// Called with the queue locked, so saves happen in the order of the changes.
+fn save_queue(control: &ControlState, queue: &VecDeque<QueuedSound>) {
+    let Some(path) = control.queue_file.lock().unwrap().clone() else {
+        return;
+    };
+    let current = control.current.lock().unwrap().clone();
+    let attention = control.attention.lock().unwrap().clone();
+    // A playing alert is both current and waiting for --ack
+    let attention = attention.filter(|alert| current.as_ref().is_none_or(|current| current.id != alert.id));
+    let unfinished: Vec<&QueuedSound> = current.iter().chain(queue).chain(attention.iter()).collect();
+    if unfinished.is_empty() {
+        let _ = std::fs::remove_file(&path);
+        return;
+    }
+    let now = SystemTime::now();
+    let saved: Vec<SavedSound> = unfinished
+        .into_iter()
+        .map(|sound| SavedSound {
+            path: sound.path.clone(),
+            alias: sound.alias.clone(),
+            options: sound.options,
+            once: sound.once.clone(),
+            queued_at: now
+                .checked_sub(sound.queued_at.elapsed())
+                .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                .map(|since| since.as_secs_f64())
+                .unwrap_or_default(),
+        })
+        .collect();
+    // Written aside and renamed over the old file, so a crash mid-write keeps the previous queue
+    let temp_path = path.with_extension("partial");
+    let written = serde_json::to_vec(&saved)
+        .map_err(std::io::Error::from)
+        .and_then(|json| std::fs::write(&temp_path, json))
+        .and_then(|_| std::fs::rename(&temp_path, &path));
+    if let Err(e) = written {
+        let _ = std::fs::remove_file(&temp_path);
+        eprintln!("Warning: Failed to save queue to {}: {}", path.display(), e);
+    }
+}
+
+// Requests a previous server saved with --persist-queue, minus those whose TTL ran out since
+fn load_saved_queue(path: &Path) -> Vec<QueuedSound> {
+    let saved: Vec<SavedSound> = match std::fs::read(path) {
+        Ok(json) => match serde_json::from_slice(&json) {
+            Ok(saved) => saved,
+            Err(e) => {
+                eprintln!("Warning: Ignoring unreadable saved queue {}: {}", path.display(), e);
+                return Vec::new();
+            }
+        },
+        Err(_) => return Vec::new(),
+    };
+    let now = SystemTime::now();
+    saved
+        .into_iter()
+        .filter_map(|saved| {
+            let waited = now
+                .duration_since(UNIX_EPOCH + Duration::from_secs_f64(saved.queued_at.max(0.0)))
+                .unwrap_or_default();
+            if saved.options.ttl.is_some_and(|ttl| waited.as_secs_f32() > ttl) {
+                return None;
+            }
+            if let Err(e) = saved.options.validate() {
+                eprintln!("Warning: Dropped saved request {}: {:#}", saved.path.display(), e);
+                return None;
+            }
+            let mut sound = QueuedSound::new(saved.path, saved.alias, saved.options).with_once(saved.once);
+            // Keep the original queue time for the TTL where the clock allows it
+            if let Some(queued_at) = Instant::now().checked_sub(waited) {
+                sound.queued_at = queued_at;
+            }
+            Some(sound)
+        })
+        .collect()
+}
+
+// Version of the lock file, control socket and remote protocols. Changes that only add optional
+// fields or commands keep it, since serde defaults let either side read the other's messages.
+// Bump it when an older binary would misread a message: both sides then refuse to talk to each
+// other with a clear error. Messages without a version predate versioning and are version 1.
+const PROTOCOL_VERSION: u32 = 1;
+
+fn legacy_protocol() -> u32 {
+    1
+}
+
+// A socket or remote message: the protocol version next to the message's own fields
+#[derive(Debug, Serialize, Deserialize)]
+struct Versioned<T> {
+    #[serde(default = "legacy_protocol")]
+    protocol: u32,
+    #[serde(flatten)]
+    message: T,
+}
+
+// The version of a message (or lock file), read first so that an incompatible one is
+// rejected instead of misparsed
+#[derive(Debug, Deserialize)]
+struct ProtocolProbe {
+    #[serde(default)]
+    pid: Option<u32>,
+    #[serde(default = "legacy_protocol")]
+    protocol: u32,
+}
+
+fn encode_message<T: Serialize>(message: T) -> Result<Vec<u8>> {
+    Ok(serde_json::to_vec(&Versioned {
+        protocol: PROTOCOL_VERSION,
+        message,
+    })?)
+}
+
+fn decode_message<T: serde::de::DeserializeOwned>(payload: &[u8], peer: &str) -> Result<T> {
+    let probe: ProtocolProbe = serde_json::from_slice(payload).context(format!("Invalid message from {}", peer))?;
+    if probe.protocol != PROTOCOL_VERSION {
+        anyhow::bail!(
+            "The {} uses protocol version {}, but this vh-notification-sound uses version {}; run the same version on both sides",
+            peer,
+            probe.protocol,
+            PROTOCOL_VERSION
+        );
+    }
+    let versioned: Versioned<T> = serde_json::from_slice(payload).context(format!("Invalid message from {}", peer))?;
+    Ok(versioned.message)
+}
+
 // Lock file information including notification state
 #[derive(Debug, Serialize, Deserialize)]
 struct LockInfo {
     pid: u32,
+    // Protocol version of the server that owns the lock
+    #[serde(default = "legacy_protocol")]
+    protocol: u32,
     state: NotificationState,
     // Used for IPC to request new notifications
     new_request: Option<String>,
+    // PID of the client that wrote new_request
+    #[serde(default)]
+    request_pid: Option<u32>,
+    // Alias that new_request was resolved from
+    #[serde(default)]
+    request_alias: Option<String>,
+    // Priority, volume and urgency of new_request
+    #[serde(default)]
+    request_options: RequestOptions,
+    // --once key of new_request
+    #[serde(default)]
+    request_once: Option<String>,
+    // Background audio level during the latest fade
+    #[serde(default)]
+    fade_progress: Option<FadeProgress>,
+    // File picked most recently from a sound directory
+    #[serde(default)]
+    last_pick: Option<String>,
+    // Source muted by --mute-mic; the next server unmutes it if this one died first
+    #[serde(default)]
+    muted_source: Option<String>,
+    // Volumes and inputs to restore while the server has the audio ducked
+    #[serde(default)]
+    ducked_audio: Option<DuckedAudio>,
+}
+
+// Audio state from before ducking, kept in the lock file so it can be restored when the
+// server dies without cleaning up (e.g. --kill)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct DuckedAudio {
+    default_sink: String,
+    volume: f32,
+    // Other sinks faded with the default one, with their original volumes
+    #[serde(default)]
+    sink_volumes: Vec<(String, f32)>,
+    // Inputs that were unmuted before ducking
+    #[serde(default)]
+    muted_inputs: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum FadeDirection {
+    Out,
+    In,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+struct FadeProgress {
+    // 0.0 = background fully faded out, 1.0 = full volume
+    level: f32,
+    direction: FadeDirection,
 }
 
 #[derive(Parser, Debug)]
@@ -50,8 +934,12 @@ struct LockInfo {
     author,
     version,
     about = "A simple application that plays notification sounds while temporarily fading out any currently playing audio. Designed for Linux systems with PulseAudio.",
-    long_about = "A simple application that plays notification sounds while temporarily fading out any currently playing audio.\nDesigned specifically for Linux systems with PulseAudio.\n\nGitHub Repository: https://github.com/vhqtvn/vh-notification-sound"
+    long_about = "A simple application that plays notification sounds while temporarily fading out any currently playing audio.\nDesigned specifically for Linux systems with PulseAudio.\n\nGitHub Repository: https://github.com/vhqtvn/vh-notification-sound",
+    // -h belongs to --help-info, so clap's own help is --help only
+    disable_help_flag = true
 )]
+// The outputs --json applies to
+#[command(group(clap::ArgGroup::new("json_output").args(["status", "list_queue"]).multiple(true)))]
 struct Args {
     /// Sound alias or path to audio file
     #[arg(index = 1)]
@@ -69,14 +957,44 @@ struct Args {
     #[arg(long, env = "VH_NOTIFICATION_FADE_IN")]
     fade_in: Option<f32>,
 
-    /// Output volume percentage for notification sound (0-100)
+    /// Shape fades with this ramp profile from the config (linear by default)
+    #[arg(long, env = "VH_NOTIFICATION_RAMP")]
+    ramp: Option<String>,
+
+    /// Output volume percentage for notification sound (0-100, fractions allowed)
     #[arg(short, long, env = "VH_NOTIFICATION_VOLUME")]
-    volume: Option<u8>,
+    volume: Option<f32>,
+
+    /// Notification volume as a percentage of the current sink volume (e.g. 50 for half as loud)
+    #[arg(long, value_name = "PERCENT", env = "VH_NOTIFICATION_VOLUME_RELATIVE", conflicts_with = "volume")]
+    volume_relative: Option<f32>,
+
+    /// Play the sound alias with this number in the --list-sounds output
+    #[arg(long, conflicts_with = "sound")]
+    index: Option<usize>,
+
+    /// Play the sound the config maps to this category (e.g. email, im, calendar, error); unmapped
+    /// categories play the fallback or a built-in tone
+    #[arg(long, conflicts_with_all = ["sound", "index"])]
+    category: Option<String>,
 
     /// Path to config file
     #[arg(short, long, env = "VH_NOTIFICATION_CONFIG")]
     config: Option<PathBuf>,
 
+    /// Ignore all config files (including --config and VH_NOTIFICATION_CONFIG): only options,
+    /// environment and built-in defaults apply
+    #[arg(long, conflicts_with = "save_defaults")]
+    no_config: bool,
+
+    /// Also look for ./vh-notification-sound.yml in the working directory, before the other locations
+    #[arg(long, env = "VH_NOTIFICATION_CWD_CONFIG")]
+    cwd_config: bool,
+
+    /// Directory for the lock file and control socket; instances with different directories are independent
+    #[arg(long, env = "VH_NOTIFICATION_RUNTIME_DIR")]
+    runtime_dir: Option<PathBuf>,
+
     /// List available sound aliases from config
     #[arg(short = 'l', long)]
     list_sounds: bool,
@@ -85,20 +1003,421 @@ struct Args {
     #[arg(short = 'h', long)]
     help_info: bool,
 
+    /// Print help
+    #[arg(long, action = clap::ArgAction::Help)]
+    help: Option<bool>,
+
     /// Detach process and run in background
     #[arg(short = 'd', long, env = "VH_NOTIFICATION_DETACH")]
     detach: bool,
+
+    /// Interval in seconds between checks for new requests and interruptions
+    #[arg(long, env = "VH_NOTIFICATION_POLL_INTERVAL")]
+    poll_interval: Option<f32>,
+
+    /// Seconds of playback after which new requests stop interrupting the current sound
+    /// (by default the sound's length, or 10 when it can't be determined)
+    #[arg(long, env = "VH_NOTIFICATION_MAX_DURATION")]
+    max_duration: Option<f32>,
+
+    /// Maximum volume and mute changes per second sent to PulseAudio/PipeWire (0 for no limit)
+    #[arg(long, env = "VH_NOTIFICATION_PACTL_RATE")]
+    pactl_rate: Option<f32>,
+
+    /// Keep the server running while idle and exit after this many idle seconds
+    #[arg(long, env = "VH_NOTIFICATION_IDLE_TIMEOUT")]
+    idle_timeout: Option<f32>,
+
+    /// Save the server's queue next to its lock file after every change and replay the requests
+    /// still within their TTL when a server starts after a crash or restart
+    #[arg(long, env = "VH_NOTIFICATION_PERSIST_QUEUE")]
+    persist_queue: bool,
+
+    /// Command used to play sounds instead of paplay; {file} is replaced with the sound path
+    /// and {sink} with the sink to play on
+    #[arg(long, env = "VH_NOTIFICATION_PLAYER_CMD")]
+    player_cmd: Option<String>,
+
+    /// Play sounds from an XDG sound theme with paplay or by event id with canberra-gtk-play
+    #[arg(long, value_enum, env = "VH_NOTIFICATION_BACKEND_PLAYER")]
+    backend_player: Option<BackendPlayer>,
+
+    /// Command run (via sh) after the fade-out, right before each sound plays
+    #[arg(long, env = "VH_NOTIFICATION_PRE_HOOK")]
+    pre_hook: Option<String>,
+
+    /// Command run (via sh) once each sound has finished, after the fade-in
+    #[arg(long, env = "VH_NOTIFICATION_POST_HOOK")]
+    post_hook: Option<String>,
+
+    /// POST every server event as JSON to this http:// URL (e.g. a Home Assistant webhook)
+    #[arg(long, value_name = "URL", env = "VH_NOTIFICATION_WEBHOOK")]
+    webhook: Option<String>,
+
+    /// Only duck streams with these media roles (e.g. music,video; "none" matches streams without a role)
+    #[arg(long, value_delimiter = ',', env = "VH_NOTIFICATION_DUCK_ROLES")]
+    duck_roles: Vec<String>,
+
+    /// Property of the notification stream as KEY=VALUE, over the defaults media.role=event and
+    /// application.name; repeatable, and an empty value drops a default
+    #[arg(long = "stream-property", value_name = "KEY=VALUE", value_parser = parse_stream_property, env = "VH_NOTIFICATION_STREAM_PROPERTY")]
+    stream_properties: Vec<(String, String)>,
+
+    /// Stay attached and print state transitions and received requests to stdout
+    #[arg(long)]
+    foreground: bool,
+
+    /// Convert WAV files whose sample rate differs from the sink's before playing (needs sox or ffmpeg)
+    #[arg(long, env = "VH_NOTIFICATION_RESAMPLE")]
+    resample: bool,
+
+    /// Vary each sound's volume randomly by up to this many percentage points either way
+    #[arg(long, env = "VH_NOTIFICATION_JITTER_VOLUME")]
+    jitter_volume: Option<f32>,
+
+    /// Vary each sound's speed and pitch randomly by up to this many percent either way (needs sox or ffmpeg)
+    #[arg(long, env = "VH_NOTIFICATION_JITTER_PITCH")]
+    jitter_pitch: Option<f32>,
+
+    /// Wake a suspended sink before the fade-out so the sound starts promptly (keeps the sink awake longer)
+    #[arg(long, env = "VH_NOTIFICATION_WARMUP")]
+    warmup: bool,
+
+    /// Also mute the default source (microphone) while the notification plays
+    #[arg(long, env = "VH_NOTIFICATION_MUTE_MIC")]
+    mute_mic: bool,
+
+    /// Fade the sink to and from the notification volume even when no stream is playing
+    #[arg(long, env = "VH_NOTIFICATION_FADE_SINK")]
+    fade_sink: bool,
+
+    /// When a new sound interrupts the playing one, briefly fade the background in and out again
+    /// between them instead of keeping it ducked (smoother, but the next sound starts later)
+    #[arg(long, env = "VH_NOTIFICATION_FADE_ON_INTERRUPT")]
+    fade_on_interrupt: bool,
+
+    /// Shorten the fades of short sounds to at most a quarter of the sound's length
+    #[arg(long, env = "VH_NOTIFICATION_ADAPTIVE_FADE")]
+    adaptive_fade: bool,
+
+    /// Keep the background ducked between an interrupted sound and the next one (the default)
+    #[arg(long, overrides_with = "fade_on_interrupt")]
+    no_fade_on_interrupt: bool,
+
+    /// Also play on these sinks (comma-separated names), setting and restoring the volume of each
+    #[arg(long, env = "VH_NOTIFICATION_SINKS", value_delimiter = ',', value_name = "SINKS", conflicts_with = "sink_all")]
+    sinks: Vec<String>,
+
+    /// Play on every sink at once, setting and restoring the volume of each
+    #[arg(long, env = "VH_NOTIFICATION_SINK_ALL")]
+    sink_all: bool,
+
+    /// Don't restore a sink whose volume was changed by someone else while the notification played
+    #[arg(long, env = "VH_NOTIFICATION_PRESERVE_USER_CHANGES")]
+    preserve_user_changes: bool,
+
+    /// Retry unmuting a stream that is still muted once the notification is over, and warn
+    /// about it (with the application's name) if it stays muted
+    #[arg(long, env = "VH_NOTIFICATION_VERIFY_RESTORE")]
+    verify_restore: bool,
+
+    /// Print how long each phase took (config load, sink probe, fades, playback) to stderr
+    #[arg(long)]
+    time: bool,
+
+    /// Write the audio state to before.json and after.json in this directory around each notification
+    #[arg(long, value_name = "DIR")]
+    snapshot: Option<PathBuf>,
+
+    /// Play the sound over other audio without muting, fading or changing any volume
+    #[arg(long, env = "VH_NOTIFICATION_NO_DUCK")]
+    no_duck: bool,
+
+    /// Priority of this request; a playing sound is only interrupted by requests of the same or higher priority
+    #[arg(long, value_enum, env = "VH_NOTIFICATION_PRIORITY")]
+    priority: Option<Priority>,
+
+    /// High priority, louder than the configured volume and not interruptible by other requests
+    #[arg(long, conflicts_with = "priority")]
+    urgent: bool,
+
+    /// Which waiting requests the server plays: the newest only, or all of them oldest or newest first
+    #[arg(long, value_enum, env = "VH_NOTIFICATION_QUEUE_POLICY")]
+    queue_policy: Option<QueuePolicy>,
+
+    /// Most requests the server keeps waiting (default 16, 0 for no limit)
+    #[arg(long, env = "VH_NOTIFICATION_MAX_QUEUE")]
+    max_queue: Option<usize>,
+
+    /// What the server does with a request arriving when its queue is full
+    #[arg(long, value_enum, env = "VH_NOTIFICATION_OVERFLOW")]
+    overflow: Option<OverflowPolicy>,
+
+    /// Flash the screen backlight while the sound plays (needs brightnessctl or a writable /sys/class/backlight)
+    #[arg(long)]
+    flash: bool,
+
+    /// Flash the screen backlight instead of playing the sound
+    #[arg(long)]
+    silent_flash: bool,
+
+    /// Repeat the sound, keeping other audio ducked, until it is acknowledged with --ack
+    #[arg(long, conflicts_with_all = ["simple", "preview"])]
+    until_ack: bool,
+
+    /// Leave other audio ducked after the sound, so the next one starts at once; --restore,
+    /// a sound without this flag or a 30 second timeout brings it back
+    #[arg(long, conflicts_with_all = ["simple", "preview"])]
+    no_restore: bool,
+
+    /// Drop this request if it has not started playing within this time (e.g. 2s, 500ms)
+    #[arg(long, value_parser = parse_duration, env = "VH_NOTIFICATION_TTL")]
+    ttl: Option<Duration>,
+
+    /// On Ctrl-C, let the sound that is playing finish before shutting down (a second Ctrl-C stops it)
+    #[arg(long)]
+    no_interrupt_current: bool,
+
+    /// Play the sound at the notification volume through paplay's stream volume, without ducking or a server
+    #[arg(long, conflicts_with_all = ["simple", "listen"])]
+    preview: bool,
+
+    /// Beep on each channel of the default sink in turn (left, right, center, ...) to check the speakers
+    #[arg(long)]
+    sweep: bool,
+
+    /// When the sound is a directory, don't pick the same file as last time (needs the notification server)
+    #[arg(long)]
+    no_repeat: bool,
+
+    /// Drop this request if the running server already played one with the same key
+    #[arg(long, value_name = "KEY", conflicts_with_all = ["simple", "preview"])]
+    once: Option<String>,
+
+    /// Forget the --once key after this time (e.g. 10m, at most 720h) instead of when the server exits
+    #[arg(long, value_parser = parse_duration, requires = "once")]
+    once_for: Option<Duration>,
+
+    /// Play the sound again at this interval (e.g. 30m, at most 720h) until --count plays are done or Ctrl-C
+    #[arg(long, value_parser = parse_every, conflicts_with_all = ["simple", "preview", "once"])]
+    every: Option<Duration>,
+
+    /// Number of plays with --every, the first one included (default: until stopped)
+    #[arg(long, requires = "every", value_parser = clap::value_parser!(u32).range(1..))]
+    count: Option<u32>,
+
+    /// Play the sound once and exit, without starting or contacting a notification server
+    #[arg(long)]
+    simple: bool,
+
+    /// Duck the background audio while the command given after -- runs, and restore it once the
+    /// command exits; no sound is played, and the exit status is the command's
+    #[arg(long, requires = "command", conflicts_with_all = ["sound", "detach", "simple", "preview", "remote", "listen", "every"])]
+    duck_while: bool,
+
+    /// Command run by --duck-while
+    #[arg(index = 2, last = true, value_name = "COMMAND", requires = "duck_while")]
+    command: Vec<String>,
+
+    /// Save the resolved fade and volume settings as defaults in the config file
+    #[arg(long)]
+    save_defaults: bool,
+
+    /// Write a starter config file (to --config or ~/.config/vh-notification-sound.yml)
+    #[arg(long)]
+    config_init: bool,
+
+    /// Overwrite an existing config file with --config-init
+    #[arg(long, requires = "config_init")]
+    force: bool,
+
+    /// Validate this config file alone and show what each alias resolves to
+    #[arg(long, value_name = "FILE")]
+    check_config: Option<PathBuf>,
+
+    /// Don't fail --check-config because of sound files that don't exist
+    #[arg(long, requires = "check_config")]
+    allow_missing: bool,
+
+    /// Accept requests over TCP on this address (port alone binds 127.0.0.1); requires --token
+    #[arg(long, env = "VH_NOTIFICATION_LISTEN", conflicts_with = "simple")]
+    listen: Option<String>,
+
+    /// Send the sound alias to a server started with --listen on host:port instead of playing it here
+    #[arg(long, requires = "sound", conflicts_with = "listen")]
+    remote: Option<String>,
+
+    /// Shared secret required by --listen and sent by --remote
+    #[arg(long, env = "VH_NOTIFICATION_TOKEN", hide_env_values = true)]
+    token: Option<String>,
+
+    /// Remove lock files and sockets left behind by notification servers that are no longer running
+    #[arg(long)]
+    clean: bool,
+
+    /// Show the state of the running notification server
+    #[arg(long)]
+    status: bool,
+
+    /// Print --status or --list-queue output as JSON
+    #[arg(long, requires = "json_output")]
+    json: bool,
+
+    /// List the running server's playing sound and queued requests with their ids
+    #[arg(long)]
+    list_queue: bool,
+
+    /// Drop the queued request with this id (see --list-queue), or stop it if it is playing
+    #[arg(long, value_name = "ID")]
+    cancel: Option<u64>,
+
+    /// Connect to the notification server and print its events as JSON lines
+    #[arg(long)]
+    subscribe: bool,
+
+    /// Clear queued notifications on the running server, letting the current sound finish
+    #[arg(long, conflicts_with = "flush_now")]
+    flush: bool,
+
+    /// Clear queued notifications on the running server and stop the current sound
+    #[arg(long)]
+    flush_now: bool,
+
+    /// Make the running server reload its config file before the next sound
+    #[arg(long)]
+    reload: bool,
+
+    /// Acknowledge the alert the running server repeats for --until-ack, stopping it
+    #[arg(long)]
+    ack: bool,
+
+    /// Fade back in the audio the running server left ducked for --no-restore
+    #[arg(long)]
+    restore: bool,
+
+    /// SIGKILL the running server (e.g. stuck in a pactl call) and restore the audio it ducked
+    #[arg(long)]
+    kill: bool,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 struct Config {
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     fade_out: Option<f32>,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     fade_in: Option<f32>,
-    #[serde(default)]
-    volume: Option<u8>,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    volume: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    poll_interval: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max_duration: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    idle_timeout: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    persist_queue: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    player_cmd: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    backend_player: Option<BackendPlayer>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    duck_roles: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    resample: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    jitter_volume: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    jitter_pitch: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    warmup: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    mute_mic: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    fade_sink: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    fade_on_interrupt: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    adaptive_fade: Option<bool>,
+    // Sinks to play on besides the default one
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    sinks: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    sink_all: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    preserve_user_changes: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    verify_restore: Option<bool>,
+    // Properties of the notification stream over the defaults; an empty value drops one
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    stream_properties: HashMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    queue_policy: Option<QueuePolicy>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max_queue: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    overflow: Option<OverflowPolicy>,
+    // Alias or path queued in place of a flood with the sound overflow policy
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    overflow_sound: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pactl_rate: Option<f32>,
+    // Default ramp profile for fades
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    ramp: Option<String>,
+    // Named fade shapes: background levels from full volume (1.0) to faded out
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    ramps: HashMap<String, Vec<f32>>,
+    // Hook commands run before and after each sound plays
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pre_play: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    post_play: Option<String>,
+    // http:// URL every server event is posted to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    webhook: Option<String>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    sounds: HashMap<String, String>,
+    // Sound alias (or path) for each notification category passed with --category
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    categories: HashMap<String, String>,
+    // Environment variable gating each alias or category: it only plays while the variable is truthy
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    enabled_if: HashMap<String, String>,
+    // Short attention sound (alias or path) played right before each alias's own sound
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    preroll: HashMap<String, String>,
+    // Alias or path played when the requested sound is neither an alias nor a file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    fallback: Option<String>,
+    // Overrides keyed by the default sink's active port (e.g. analog-output-headphones)
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    ports: HashMap<String, PortOverride>,
+    // Notification volume keyed by the default sink's name, instead of the global volume
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    sink_volumes: HashMap<String, f32>,
+    // Time-of-day rules (e.g. quieter sounds at night); the first matching rule applies
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    schedule: Vec<ScheduleRule>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct PortOverride {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    volume: Option<f32>,
+    // Alias remaps that take precedence over the top-level sounds
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    sounds: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct ScheduleRule {
+    // Local time range "HH:MM-HH:MM"; it may wrap past midnight (22:00-07:00)
+    time: String,
+    // Upper limit for the volume of non-urgent sounds while the rule applies
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    volume: Option<f32>,
+    // Aliases played instead of the requested ones while the rule applies
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
     sounds: HashMap<String, String>,
 }
 
@@ -107,26 +1426,105 @@ impl Default for Config {
         Self {
             fade_out: Some(0.3),
             fade_in: Some(0.3),
-            volume: Some(75),
+            volume: Some(75.0),
+            poll_interval: Some(DEFAULT_POLL_INTERVAL),
+            max_duration: None,
+            idle_timeout: None,
+            persist_queue: None,
+            player_cmd: None,
+            backend_player: None,
+            duck_roles: Vec::new(),
+            resample: None,
+            jitter_volume: None,
+            jitter_pitch: None,
+            warmup: None,
+            mute_mic: None,
+            fade_sink: None,
+            fade_on_interrupt: None,
+            adaptive_fade: None,
+            sinks: Vec::new(),
+            sink_all: None,
+            preserve_user_changes: None,
+            verify_restore: None,
+            stream_properties: HashMap::new(),
+            queue_policy: None,
+            max_queue: None,
+            overflow: None,
+            overflow_sound: None,
+            pactl_rate: None,
+            ramp: None,
+            ramps: HashMap::new(),
+            pre_play: None,
+            post_play: None,
+            webhook: None,
             sounds: HashMap::new(),
+            categories: HashMap::new(),
+            enabled_if: HashMap::new(),
+            preroll: HashMap::new(),
+            fallback: None,
+            ports: HashMap::new(),
+            sink_volumes: HashMap::new(),
+            schedule: Vec::new(),
         }
     }
 }
 
 struct PulseAudioState {
     default_sink: String,
-    current_volume: u8,
+    current_volume: f32,
     unmuted_inputs: Vec<String>,
+    // Sinks the unmuted inputs play on, with their volume before ducking
+    duck_sinks: Vec<(String, f32)>,
+    // Other sinks the notification plays on, with their volume before it
+    mirror_sinks: Vec<(String, f32)>,
+    active_port: Option<String>,
+    // False when the default sink is a monitor whose volume cannot be controlled
+    volume_settable: bool,
+    // Sample rate from the default sink's sample specification
+    sample_rate: Option<u32>,
 }
 
 // AudioStateGuard ensures cleanup happens when it goes out of scope
 struct AudioStateGuard {
     default_sink: String,
-    current_volume: u8,
+    current_volume: f32,
     unmuted_inputs: Vec<String>,
+    // Sinks faded for ducking: where the unmuted inputs play, which need not be the
+    // default sink the notification plays on
+    duck_sinks: Vec<(String, f32)>,
+    // Sinks the notification plays on besides the default one (--sinks, --sink-all); each
+    // gets the notification volume and its own volume back afterwards
+    mirror_sinks: Vec<(String, f32)>,
+    volume_settable: bool,
+    sample_rate: Option<u32>,
+    // Lock file that receives fade progress (server only)
+    lock_path: Option<PathBuf>,
+    // Where a SIGUSR1 state dump goes when it arrives mid-fade (server only)
+    dump_target: Option<DumpTarget>,
+    // Background levels the fades interpolate through, from full volume to faded out
+    ramp: Option<Vec<f32>>,
+    // Mute the default source while a notification plays
+    mute_mic: bool,
+    // Fade the default sink to the notification volume when no input fade ducks it
+    fade_sink: bool,
+    // Source muted by us that still has to be unmuted
+    muted_source: Option<String>,
+    // Leave a sink alone once its volume is no longer the one we set
+    preserve_user_changes: bool,
+    // Retry unmutes that did not take before reporting them
+    verify_restore: bool,
+    // Volume we last set on each sink, and the sinks someone else has changed since
+    applied_volumes: HashMap<String, f32>,
+    user_changed_sinks: Vec<String>,
+    // Directory receiving --snapshot files; the after snapshot is pending once before is written
+    snapshot_dir: Option<PathBuf>,
+    snapshot_pending: bool,
     cleaned_up: bool,
-    // Current fade state (0 = fully faded out, FADE_STEPS = full volume)
+    // Current fade state (0 = fully faded out, FADE_STEPS = full volume); always the
+    // last step actually applied, so an interrupted fade can be reversed without a jump
     fade_state: u8,
+    // Inputs muted and volume set for the notification, until the restore starts
+    ducked: bool,
     needs_restore_volume: bool,
     needs_unmute_inputs: bool,
     cleanup_signal: Arc<AtomicBool>,
@@ -138,14 +1536,49 @@ impl AudioStateGuard {
             default_sink: state.default_sink,
             current_volume: state.current_volume,
             unmuted_inputs: state.unmuted_inputs,
+            duck_sinks: state.duck_sinks,
+            mirror_sinks: state.mirror_sinks,
+            volume_settable: state.volume_settable,
+            sample_rate: state.sample_rate,
+            lock_path: None,
+            dump_target: None,
+            ramp: None,
+            mute_mic: false,
+            fade_sink: false,
+            muted_source: None,
+            preserve_user_changes: false,
+            verify_restore: false,
+            applied_volumes: HashMap::new(),
+            user_changed_sinks: Vec::new(),
+            snapshot_dir: None,
+            snapshot_pending: false,
             needs_restore_volume: false,
             needs_unmute_inputs: false,
             cleaned_up: false,
             fade_state: FADE_STEPS, // Start at full volume
+            ducked: false,
             cleanup_signal: Arc::new(AtomicBool::new(false)),
         }
     }
 
+    /// Replace the captured state with a fresh snapshot; only valid once audio is restored
+    fn refresh(&mut self, state: PulseAudioState) {
+        if state.default_sink != self.default_sink {
+            eprintln!("Default sink changed from {} to {}", self.default_sink, state.default_sink);
+        }
+        self.default_sink = state.default_sink;
+        self.current_volume = state.current_volume;
+        self.unmuted_inputs = state.unmuted_inputs;
+        self.duck_sinks = state.duck_sinks;
+        self.mirror_sinks = state.mirror_sinks;
+        self.volume_settable = state.volume_settable;
+        self.sample_rate = state.sample_rate;
+        self.fade_state = FADE_STEPS;
+        // Volumes the user changed are part of the fresh state
+        self.applied_volumes.clear();
+        self.user_changed_sinks.clear();
+    }
+
     pub fn set_needs_restore_volume(&mut self) {
         self.needs_restore_volume = true;
         self.cleaned_up = false;
@@ -164,41 +1597,185 @@ impl AudioStateGuard {
         Ok(())
     }
 
-    fn set_volume(&mut self, volume: u8) -> Result<()> {
-        self.set_needs_restore_volume();
-        pactl!("set-sink-volume", &self.default_sink, &format!("{}%", volume))?;
+    /// Mute the default source unless it is muted already (then it is left alone)
+    fn mute_source(&mut self) -> Result<()> {
+        if self.muted_source.is_some() {
+            return Ok(());
+        }
+        let source = get_default_source()?;
+        if source_muted(&source)? {
+            return Ok(());
+        }
+
+        // Record the source before muting it, so a crash right after still gets it unmuted
+        self.muted_source = Some(source.clone());
+        self.cleaned_up = false;
+        self.report_muted_source();
+        pactl!("set-source-mute", &source, "1")?;
         Ok(())
     }
 
-    fn cleanup(&mut self) -> Result<()> {
-        if self.cleaned_up {
-            return Ok(());
+    fn unmute_source(&mut self) -> Result<()> {
+        if let Some(source) = &self.muted_source {
+            pactl!("set-source-mute", source, "0")?;
+            self.muted_source = None;
+            self.report_muted_source();
         }
+        Ok(())
+    }
 
-        // Signal any running fades to stop
-        self.cleanup_signal.store(true, Ordering::SeqCst);
+    /// Keep the audio state from before ducking in the lock file while it is changed
+    fn report_ducked_audio(&self, ducked: bool) {
+        let Some(lock_path) = &self.lock_path else {
+            return;
+        };
+        if let Ok(mut lock_info) = read_lock_file(lock_path) {
+            lock_info.ducked_audio = ducked.then(|| DuckedAudio {
+                default_sink: self.default_sink.clone(),
+                volume: self.current_volume,
+                sink_volumes: self.restored_sinks().into_iter().skip(1).collect(),
+                muted_inputs: self.unmuted_inputs.clone(),
+            });
+            let _ = update_lock_file(lock_path, &lock_info);
+        }
+    }
+
+    /// Keep the muted source in the lock file for recovery after a crash
+    fn report_muted_source(&self) {
+        let Some(lock_path) = &self.lock_path else {
+            return;
+        };
+        if let Ok(mut lock_info) = read_lock_file(lock_path) {
+            lock_info.muted_source = self.muted_source.clone();
+            let _ = update_lock_file(lock_path, &lock_info);
+        }
+    }
+
+    fn set_volume(&mut self, volume: f32) -> Result<()> {
+        self.set_needs_restore_volume();
+        for (sink, _) in self.notification_sinks() {
+            self.apply_sink_volume(&sink, volume)?;
+        }
+        Ok(())
+    }
+
+    /// The sinks the notification plays on, default sink first, with their volumes before it
+    fn notification_sinks(&self) -> Vec<(String, f32)> {
+        std::iter::once((self.default_sink.clone(), self.current_volume))
+            .chain(self.mirror_sinks.iter().cloned())
+            .collect()
+    }
+
+    /// Every sink whose volume we may change, default sink first, each once with its volume
+    /// from before the notification
+    fn restored_sinks(&self) -> Vec<(String, f32)> {
+        let mut sinks = self.notification_sinks();
+        for (sink, volume) in &self.duck_sinks {
+            if !sinks.iter().any(|(name, _)| name == sink) {
+                sinks.push((sink.clone(), *volume));
+            }
+        }
+        sinks
+    }
+
+    /// Set a sink's volume, remembering it so a later change by someone else can be told
+    /// apart; sinks the user has changed are left alone
+    fn apply_sink_volume(&mut self, sink: &str, volume: f32) -> Result<()> {
+        if self.user_changed_sinks.iter().any(|changed| changed == sink) {
+            return Ok(());
+        }
+        pactl!("set-sink-volume", sink, &volume_arg(volume))?;
+        self.applied_volumes.insert(sink.to_string(), volume);
+        Ok(())
+    }
+
+    /// With --preserve-user-changes, stop managing every sink whose volume no longer matches
+    /// the one we set last, so the restore doesn't undo a change made during the notification
+    fn detect_user_changes(&mut self) {
+        if !self.preserve_user_changes || self.applied_volumes.is_empty() {
+            return;
+        }
+        let Ok(sinks_output) = pactl!("list", "sinks") else {
+            return;
+        };
+        for (sink, applied) in &self.applied_volumes {
+            let volumes = channel_volumes(&sink_block(&sinks_output, sink));
+            let changed = volumes.iter().any(|volume| (volume - applied).abs() > USER_CHANGE_TOLERANCE);
+            if changed && !self.user_changed_sinks.contains(sink) {
+                eprintln!("Volume of {} was changed to {}% during the notification, leaving it", sink, volumes[0]);
+                self.user_changed_sinks.push(sink.clone());
+            }
+        }
+    }
+
+    /// cleanup for the server, which carries on (or exits) either way: the failure is logged
+    /// and what failed stays pending for the next attempt
+    fn cleanup_or_warn(&mut self) {
+        if let Err(e) = self.cleanup() {
+            eprintln!("Warning: Failed to restore audio: {:#}", e);
+        }
+    }
+
+    /// Restore the original volume and unmute inputs. This never looks at the running flag,
+    /// and whatever fails stays pending so the next call (at the latest from Drop) retries it
+    fn cleanup(&mut self) -> Result<()> {
+        if self.cleaned_up {
+            return Ok(());
+        }
+
+        // Signal any running fades to stop
+        self.cleanup_signal.store(true, Ordering::SeqCst);
 
         let mut errors = Vec::new();
 
         // Restore original volume
         if self.needs_restore_volume {
-            if let Err(e) = pactl!("set-sink-volume", &self.default_sink, &format!("{}%", self.current_volume)) {
-                errors.push(format!("Failed to restore volume: {}", e));
+            self.detect_user_changes();
+            match self.restore_volumes() {
+                Ok(_) => self.needs_restore_volume = false,
+                Err(e) => errors.push(format!("Failed to restore volume: {}", e)),
             }
-            self.needs_restore_volume = false;
         }
 
         // Unmute streams that were unmuted initially
         if self.needs_unmute_inputs {
+            let mut unmuted_all = true;
             for input in &self.unmuted_inputs {
                 if let Err(e) = pactl!("set-sink-input-mute", input, "0") {
                     errors.push(format!("Failed to unmute input {}: {}", input, e));
+                    unmuted_all = false;
+                }
+            }
+            self.needs_unmute_inputs = !unmuted_all;
+
+            // pactl can succeed without the stream ending up unmuted, so ask again what it reports
+            if unmuted_all && !self.unmuted_inputs.is_empty() {
+                match self.still_muted_inputs() {
+                    Ok(inputs) => {
+                        for input in inputs {
+                            eprintln!(
+                                "Warning: Input {} ({}) is still muted after the notification{}",
+                                input.id,
+                                input.app_name.as_deref().unwrap_or("unknown application"),
+                                if self.verify_restore { "" } else { "; --verify-restore retries the unmute" }
+                            );
+                        }
+                    }
+                    Err(e) => eprintln!("Warning: Failed to check that inputs were unmuted: {}", e),
                 }
             }
-            self.needs_unmute_inputs = false;
         }
 
-        self.cleaned_up = true;
+        if let Err(e) = self.unmute_source() {
+            errors.push(format!("Failed to unmute source: {}", e));
+        }
+
+        self.cleaned_up = errors.is_empty();
+        if self.cleaned_up {
+            self.ducked = false;
+            self.report_ducked_audio(false);
+            self.write_after_snapshot();
+        }
 
         match errors.len() {
             0 => Ok(()),
@@ -210,12 +1787,40 @@ impl AudioStateGuard {
         }
     }
 
+    /// The inputs we unmuted that are still there and muted; with --verify-restore their unmute
+    /// is retried up to VERIFY_RESTORE_RETRIES times first
+    fn still_muted_inputs(&self) -> Result<Vec<SinkInput>> {
+        let mut retries = 0;
+        loop {
+            let muted: Vec<SinkInput> = get_sink_inputs()?
+                .into_iter()
+                .filter(|input| input.muted && self.unmuted_inputs.contains(&input.id))
+                .collect();
+            if muted.is_empty() || !self.verify_restore || retries == VERIFY_RESTORE_RETRIES {
+                return Ok(muted);
+            }
+            retries += 1;
+            thread::sleep(VERIFY_RESTORE_DELAY);
+            for input in &muted {
+                _ = pactl!("set-sink-input-mute", &input.id, "0");
+            }
+        }
+    }
+
     /// Prepare audio for notification by fading out and muting if needed
-    fn prepare_for_notification(&mut self, fade_out: f32, enable_fading: bool, enable_volume_control: bool, volume: u8, running: &Arc<AtomicBool>) -> Result<()> {
-        // Only prepare if not already prepared
-        if self.fade_state == FADE_STEPS {
+    fn prepare_for_notification(&mut self, fade_out: f32, enable_fading: bool, enable_volume_control: bool, volume: f32, running: &Arc<AtomicBool>) -> Result<()> {
+        // Only prepare if not already prepared; a fade-in cut short by a new request
+        // is reversed from the level it reached
+        if !self.ducked {
+            if let Some(dir) = self.snapshot_dir.as_ref().filter(|_| !self.snapshot_pending) {
+                write_snapshot(dir, "before");
+                self.snapshot_pending = true;
+            }
+            // Record the original state before touching it, so it survives a SIGKILL
+            self.report_ducked_audio(true);
+
             // Fade out if needed and we have active audio streams
-            if enable_fading && fade_out > 0.0 && running.load(Ordering::SeqCst) {
+            if enable_fading && fade_out > 0.0 && self.volume_settable && running.load(Ordering::SeqCst) {
                 self.fade_out(fade_out, running)?;
             } else {
                 // If we're skipping the fade out, set fade_state to 0 (fully faded out)
@@ -227,24 +1832,55 @@ impl AudioStateGuard {
                 return Ok(());
             }
 
+            if self.mute_mic {
+                if let Err(e) = self.mute_source() {
+                    eprintln!("Warning: Failed to mute the microphone: {}", e);
+                }
+            }
+
             if enable_fading {
                 self.mute_inputs()?;
                 // Restore volume to original level so notification can play at full volume
                 // The existing streams are muted, so this won't be audible
                 if self.needs_restore_volume {
-                    pactl!("set-sink-volume", &self.default_sink, &format!("{}%", self.current_volume))?;
+                    self.restore_volumes()?;
                 }
             }
 
-            if enable_volume_control {
-                self.set_volume(volume)?;
+            if enable_volume_control && self.volume_settable {
+                if self.fade_sink && !enable_fading && fade_out > 0.0 {
+                    self.fade_sink_volume(Some(volume), fade_out, running, &|| false);
+                } else {
+                    self.set_volume(volume)?;
+                }
             }
+            self.ducked = true;
         }
         Ok(())
     }
 
-    /// Restore audio state after notification by unmuting and fading in if needed
-    fn restore_after_notification(&mut self, fade_in: f32, enable_fading: bool, running: &Arc<AtomicBool>) -> Result<()> {
+    /// Restore audio state after notification by unmuting and fading in if needed.
+    /// The fade-in stops early when `interrupt` returns true, e.g. for a new request
+    fn restore_after_notification(&mut self, fade_in: f32, enable_fading: bool, running: &Arc<AtomicBool>, interrupt: &dyn Fn() -> bool) -> Result<()> {
+        // A failed unmute stays pending for cleanup
+        if let Err(e) = self.unmute_source() {
+            eprintln!("Warning: Failed to unmute the microphone: {}", e);
+        }
+        self.detect_user_changes();
+
+        let fading_in = enable_fading && fade_in > 0.0 && self.volume_settable && running.load(Ordering::SeqCst);
+        if fading_in {
+            // Start from the faded-out level before the inputs are audible again,
+            // rather than the notification volume
+            for (sink, volume) in self.notification_sinks() {
+                if !self.duck_sinks.iter().any(|(name, _)| *name == sink) {
+                    _ = self.apply_sink_volume(&sink, volume);
+                }
+            }
+            self.set_fade_level(self.fade_state);
+        }
+        self.ducked = false;
+
         if enable_fading {
             // Unmute all previously unmuted inputs
             for input in &self.unmuted_inputs {
@@ -253,82 +1889,202 @@ impl AudioStateGuard {
         }
 
         // Fade in if needed
-        if enable_fading && fade_in > 0.0 && running.load(Ordering::SeqCst) {
-            self.fade_in(fade_in, running)?;
+        if fading_in {
+            self.fade_in(fade_in, running, interrupt)?;
         } else {
+            // With --fade-sink the sinks glide back when no input fade does it; cut short by a
+            // new request, the next sound fades on from the level reached
+            let fading_sink = self.fade_sink
+                && !enable_fading
+                && fade_in > 0.0
+                && self.volume_settable
+                && self.needs_restore_volume
+                && running.load(Ordering::SeqCst);
+            if fading_sink && !self.fade_sink_volume(None, fade_in, running, interrupt) {
+                return Ok(());
+            }
             // If we skipped fade-in, make sure volume is restored
             if self.needs_restore_volume {
-                _ = pactl!("set-sink-volume", &self.default_sink, &format!("{}%", self.current_volume));
+                _ = self.restore_volumes();
             }
             self.fade_state = FADE_STEPS; // Fully faded in
         }
+        // An interrupted fade-in leaves the after snapshot to cleanup
+        if self.fade_state == FADE_STEPS {
+            self.report_ducked_audio(false);
+            self.write_after_snapshot();
+        }
+        Ok(())
+    }
+
+    fn write_after_snapshot(&mut self) {
+        if let Some(dir) = self.snapshot_dir.as_ref().filter(|_| self.snapshot_pending) {
+            write_snapshot(dir, "after");
+            self.snapshot_pending = false;
+        }
+    }
+
+    /// The guard's part of a StateDump; write_state_dump adds the lock file state and the queue
+    fn state_dump(&self, audio_already_prepared: bool) -> StateDump {
+        StateDump {
+            pid: std::process::id(),
+            state: None,
+            fade_state: self.fade_state,
+            ducked: self.ducked,
+            audio_already_prepared,
+            default_sink: self.default_sink.clone(),
+            current_volume: self.current_volume,
+            duck_sinks: self.duck_sinks.clone(),
+            volume_settable: self.volume_settable,
+            unmuted_inputs: self.unmuted_inputs.clone(),
+            muted_source: self.muted_source.clone(),
+            needs_restore_volume: self.needs_restore_volume,
+            needs_unmute_inputs: self.needs_unmute_inputs,
+            queue: None,
+        }
+    }
+
+    /// Answer a SIGUSR1 between fade steps instead of only once the sound is over
+    fn dump_if_requested(&self) {
+        let Some(target) = &self.dump_target else {
+            return;
+        };
+        if DUMP_REQUESTED.swap(false, Ordering::SeqCst) {
+            write_state_dump(&mut self.state_dump(target.audio_already_prepared), &target.control, &target.lock_path, target.detached);
+        }
+    }
+
+    /// Publish the fade level in the lock file for status readers
+    fn report_fade_progress(&self, direction: FadeDirection) {
+        let Some(lock_path) = &self.lock_path else {
+            return;
+        };
+        if let Ok(mut lock_info) = read_lock_file(lock_path) {
+            lock_info.fade_progress = Some(FadeProgress {
+                level: self.step_level(self.fade_state),
+                direction,
+            });
+            let _ = update_lock_file(lock_path, &lock_info);
+        }
+    }
+
+    /// Background volume corresponding to a fade step
+    fn step_volume(&self, step: u8) -> f32 {
+        self.current_volume * self.step_level(step)
+    }
+
+    /// Background level (0.0 - 1.0) at a fade step: linear, or following the ramp profile
+    fn step_level(&self, step: u8) -> f32 {
+        let level = step as f32 / FADE_STEPS as f32;
+        match &self.ramp {
+            Some(points) => ramp_level(points, 1.0 - level),
+            None => level,
+        }
+    }
+
+    /// Set every ducked sink to its level for one fade step. A failed step is retried once
+    /// and then skipped, leaving the final restore in cleanup to fix the volume up
+    fn set_fade_level(&mut self, step: u8) {
+        let level = self.step_level(step);
+        for (sink, original) in self.duck_sinks.clone() {
+            let volume = original * level;
+            if self.apply_sink_volume(&sink, volume).is_ok() {
+                continue;
+            }
+            if let Err(e) = self.apply_sink_volume(&sink, volume) {
+                eprintln!("Warning: Failed to set fade volume of {} to {}: {}", sink, volume_arg(volume), e);
+            }
+        }
+    }
+
+    /// Set the sinks played on and every ducked sink back to their volumes before the notification
+    fn restore_volumes(&mut self) -> Result<()> {
+        for (sink, volume) in self.restored_sinks() {
+            self.apply_sink_volume(&sink, volume)?;
+        }
         Ok(())
     }
 
     fn fade_out(&mut self, fade_out: f32, running: &Arc<AtomicBool>) -> Result<()> {
         self.cleanup_signal.store(false, Ordering::SeqCst);
         self.set_needs_restore_volume();
-        // Use the existing fade_state as the starting point
+        // The volume is already at the existing fade_state (full volume, or wherever an
+        // interrupted fade-in stopped), so the fade continues with the next step down
         let start_step = self.fade_state;
         let fade_out_step_duration = Duration::from_secs_f32(fade_out / FADE_STEPS as f32);
 
-        // Starting from current fade_state and going down to 0
-        // Include start_step in the loop so we start from full volume
-        for step in (0..=start_step).rev() {
+        for step in (0..start_step).rev() {
+            thread::sleep(fade_out_step_duration);
+            self.dump_if_requested();
             if !running.load(Ordering::SeqCst) || self.cleanup_signal.load(Ordering::SeqCst) {
-                // Remember the current fade state before exiting
-                self.fade_state = step;
                 break;
             }
 
-            let volume_factor = step as f32 / FADE_STEPS as f32;
-            let step_volume = (self.current_volume as f32 * volume_factor) as u8;
-
-            pactl!("set-sink-volume", &self.default_sink, &format!("{}%", step_volume))?;
+            self.set_fade_level(step);
 
             // Update the fade state after each step
             self.fade_state = step;
-
-            // Don't sleep after the last step
-            if step > 0 {
-                thread::sleep(fade_out_step_duration);
-            }
+            self.report_fade_progress(FadeDirection::Out);
         }
 
         Ok(())
     }
 
-    fn fade_in(&mut self, fade_in: f32, running: &Arc<AtomicBool>) -> Result<()> {
+    /// Move the sinks played on from the volume we set last (their own volume at first) to `to`,
+    /// or each back to its volume before the notification, in fade steps; false when the fade
+    /// was stopped before reaching it
+    fn fade_sink_volume(&mut self, to: Option<f32>, duration: f32, running: &Arc<AtomicBool>, interrupt: &dyn Fn() -> bool) -> bool {
+        self.cleanup_signal.store(false, Ordering::SeqCst);
+        self.set_needs_restore_volume();
+        let fades: Vec<(String, f32, f32)> = self
+            .notification_sinks()
+            .into_iter()
+            .map(|(sink, original)| {
+                let from = self.applied_volumes.get(&sink).copied().unwrap_or(original);
+                (sink, from, to.unwrap_or(original))
+            })
+            .collect();
+        let step_duration = Duration::from_secs_f32(duration / FADE_STEPS as f32);
+
+        for step in 1..=FADE_STEPS {
+            thread::sleep(step_duration);
+            self.dump_if_requested();
+            if !running.load(Ordering::SeqCst) || self.cleanup_signal.load(Ordering::SeqCst) || interrupt() {
+                return false;
+            }
+            for (sink, from, to) in &fades {
+                let volume = from + (to - from) * step as f32 / FADE_STEPS as f32;
+                if let Err(e) = self.apply_sink_volume(sink, volume) {
+                    eprintln!("Warning: Failed to set fade volume of {} to {}: {}", sink, volume_arg(volume), e);
+                }
+            }
+        }
+        true
+    }
+
+    fn fade_in(&mut self, fade_in: f32, running: &Arc<AtomicBool>, interrupt: &dyn Fn() -> bool) -> Result<()> {
         self.cleanup_signal.store(false, Ordering::SeqCst);
-        // Use the existing fade_state as the starting point
+        // The volume is already at the existing fade_state, so start with the next step up
         let start_step = self.fade_state;
         let fade_in_step_duration = Duration::from_secs_f32(fade_in / FADE_STEPS as f32);
 
-        // Starting from current fade_state and going up to FADE_STEPS
-        for step in start_step..=FADE_STEPS {
-            if !running.load(Ordering::SeqCst) || self.cleanup_signal.load(Ordering::SeqCst) {
-                // Remember the current fade state before exiting
-                self.fade_state = step;
+        for step in start_step + 1..=FADE_STEPS {
+            thread::sleep(fade_in_step_duration);
+            self.dump_if_requested();
+            if !running.load(Ordering::SeqCst) || self.cleanup_signal.load(Ordering::SeqCst) || interrupt() {
                 break;
             }
 
-            let volume_factor = step as f32 / FADE_STEPS as f32;
-            let step_volume = (self.current_volume as f32 * volume_factor) as u8;
-
-            pactl!("set-sink-volume", &self.default_sink, &format!("{}%", step_volume))?;
+            self.set_fade_level(step);
 
             // Update the fade state after each step
             self.fade_state = step;
-
-            // Don't sleep after the last step
-            if step < FADE_STEPS {
-                thread::sleep(fade_in_step_duration);
-            }
+            self.report_fade_progress(FadeDirection::In);
         }
 
-        // Final volume restoration only if not cleaning up
-        if !self.cleanup_signal.load(Ordering::SeqCst) {
-            pactl!("set-sink-volume", &self.default_sink, &format!("{}%", self.current_volume))?;
+        // Final volume restoration only once the fade has completed
+        if self.fade_state == FADE_STEPS && !self.cleanup_signal.load(Ordering::SeqCst) {
+            _ = self.restore_volumes();
         }
 
         Ok(())
@@ -342,15 +2098,322 @@ impl Drop for AudioStateGuard {
     }
 }
 
-// Add this struct before the play_notification function
-struct NotificationContext<'a> {
-    sound_path: PathBuf,
+// Removes the server's lock file, control socket and pitched copy however the server returns.
+// Declared before the AudioStateGuard, so it drops after it: the lock (with the audio to
+// recover) stays until the guard's last cleanup attempt is over
+struct ServerFiles {
+    lock_path: PathBuf,
+    socket_path: PathBuf,
+}
+
+impl Drop for ServerFiles {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.lock_path);
+        let _ = std::fs::remove_file(&self.socket_path);
+        let _ = std::fs::remove_file(pitched_sound_path(&self.lock_path));
+    }
+}
+
+enum BacklightControl {
+    Brightnessctl,
+    // brightness file of a /sys/class/backlight device
+    Sysfs(PathBuf),
+}
+
+// BacklightGuard restores the original screen brightness when it goes out of scope
+struct BacklightGuard {
+    control: BacklightControl,
+    original: u32,
+}
+
+impl BacklightGuard {
+    /// Find a usable backlight control; None when the brightness can't be changed
+    fn new() -> Option<Self> {
+        if let Ok(original) = run_command("brightnessctl", &["get"]).map(|value| value.parse::<u32>()) {
+            return Some(BacklightGuard {
+                control: BacklightControl::Brightnessctl,
+                original: original.ok()?,
+            });
+        }
+
+        let device = std::fs::read_dir("/sys/class/backlight").ok()?.flatten().next()?.path();
+        let brightness_path = device.join("brightness");
+        // Writing usually needs root or a udev rule
+        OpenOptions::new().write(true).open(&brightness_path).ok()?;
+        let original = std::fs::read_to_string(&brightness_path).ok()?.trim().parse().ok()?;
+        Some(BacklightGuard {
+            control: BacklightControl::Sysfs(brightness_path),
+            original,
+        })
+    }
+
+    fn set_brightness(&self, value: u32) -> Result<()> {
+        match &self.control {
+            BacklightControl::Brightnessctl => run_command("brightnessctl", &["-q", "set", &value.to_string()]).map(|_| ()),
+            BacklightControl::Sysfs(path) => std::fs::write(path, value.to_string())
+                .context(format!("Failed to write {}", path.display())),
+        }
+    }
+
+    /// Dip the brightness and bring it back a few times
+    fn flash(&self, running: &Arc<AtomicBool>) {
+        let dimmed = (self.original as f32 * FLASH_DIM_LEVEL) as u32;
+        for _ in 0..FLASH_PULSES {
+            if !running.load(Ordering::SeqCst) {
+                break;
+            }
+            if let Err(e) = self.set_brightness(dimmed) {
+                eprintln!("Warning: Failed to flash backlight: {}", e);
+                break;
+            }
+            thread::sleep(FLASH_PHASE);
+            let _ = self.set_brightness(self.original);
+            thread::sleep(FLASH_PHASE);
+        }
+    }
+}
+
+impl Drop for BacklightGuard {
+    fn drop(&mut self) {
+        let _ = self.set_brightness(self.original);
+    }
+}
+
+// Flash the backlight on its own thread; None when there is no backlight to control
+fn start_flash(running: &Arc<AtomicBool>) -> Option<thread::JoinHandle<()>> {
+    let Some(backlight) = BacklightGuard::new() else {
+        eprintln!("Warning: No controllable backlight found, skipping flash");
+        return None;
+    };
+    let running = running.clone();
+    Some(thread::spawn(move || backlight.flash(&running)))
+}
+
+// Phase timestamps collected for --time
+struct Timings {
+    start: Instant,
+    last: Instant,
+    phases: Vec<(&'static str, Duration, Duration)>,
+}
+
+static TIMINGS: Mutex<Option<Timings>> = Mutex::new(None);
+
+fn start_timing() {
+    let now = Instant::now();
+    *TIMINGS.lock().unwrap() = Some(Timings {
+        start: now,
+        last: now,
+        phases: Vec::new(),
+    });
+}
+
+// Record the time spent since the previous phase; a no-op unless --time is given
+fn mark_phase(name: &'static str) {
+    if let Some(timings) = TIMINGS.lock().unwrap().as_mut() {
+        let now = Instant::now();
+        let phase = (name, now - timings.last, now - timings.start);
+        timings.phases.push(phase);
+        timings.last = now;
+    }
+}
+
+fn print_timings() {
+    let Some(timings) = TIMINGS.lock().unwrap().take() else {
+        return;
+    };
+
+    eprintln!("{:<16} {:>10} {:>10}", "PHASE", "DURATION", "AT");
+    for (name, duration, at) in &timings.phases {
+        eprintln!(
+            "{:<16} {:>7.1} ms {:>7.1} ms",
+            name,
+            duration.as_secs_f64() * 1000.0,
+            at.as_secs_f64() * 1000.0
+        );
+    }
+    let total = timings.start.elapsed();
+    eprintln!("{:<16} {:>7.1} ms", "total", total.as_secs_f64() * 1000.0);
+}
+
+// Interpolate a ramp profile at `progress` (0.0 = full volume end, 1.0 = faded out end)
+fn ramp_level(points: &[f32], progress: f32) -> f32 {
+    let position = progress.clamp(0.0, 1.0) * (points.len() - 1) as f32;
+    let index = (position.floor() as usize).min(points.len() - 2);
+    let fraction = position - index as f32;
+    points[index] + (points[index + 1] - points[index]) * fraction
+}
+
+// Look up a ramp profile and check it describes a fade-out
+fn resolve_ramp(config: &Config, name: &str) -> Result<Vec<f32>> {
+    let points = config
+        .ramps
+        .get(name)
+        .context(format!("Unknown ramp profile: {} (define it under 'ramps' in the config)", name))?;
+    if points.len() < 2 {
+        anyhow::bail!("Ramp profile {} needs at least two points", name);
+    }
+    if points.iter().any(|point| !(0.0..=1.0).contains(point)) {
+        anyhow::bail!("Ramp profile {} has points outside 0.0 - 1.0", name);
+    }
+    if points.windows(2).any(|pair| pair[1] > pair[0]) {
+        anyhow::bail!("Ramp profile {} must not increase (it runs from full volume to faded out)", name);
+    }
+    Ok(points.clone())
+}
+
+// Runs the post-play hook when playback of a sound is over, however play_notification returns
+struct PostPlayHook<'a> {
+    command: Option<&'a str>,
+    sound: &'a QueuedSound,
+}
+
+impl Drop for PostPlayHook<'_> {
+    fn drop(&mut self) {
+        if let Some(command) = self.command {
+            run_hook("post-play", command, self.sound);
+        }
+    }
+}
+
+// Minimum spacing between state-changing pactl calls
+struct RateLimiter {
+    min_gap: Duration,
+    last: Option<Instant>,
+}
+
+// Interrupt signals received; a second one stops a sound kept playing by --no-interrupt-current
+static SHUTDOWN_SIGNALS: AtomicUsize = AtomicUsize::new(0);
+
+static PACTL_LIMITER: Mutex<RateLimiter> = Mutex::new(RateLimiter {
+    min_gap: Duration::ZERO,
+    last: None,
+});
+
+// Space state-changing pactl calls at least this far apart (see pactl_gap)
+fn set_pactl_rate(min_gap: Duration) {
+    PACTL_LIMITER.lock().unwrap().min_gap = min_gap;
+}
+
+// Gap between state-changing pactl calls for a rate per second; 0 disables the limit
+fn pactl_gap(calls_per_sec: f32) -> Result<Duration> {
+    if calls_per_sec == 0.0 {
+        return Ok(Duration::ZERO);
+    }
+    if !calls_per_sec.is_finite() || calls_per_sec < MIN_PACTL_RATE {
+        anyhow::bail!("pactl_rate must be 0 or at least {} calls per second, not {}", MIN_PACTL_RATE, calls_per_sec);
+    }
+    Ok(Duration::from_secs_f32(1.0 / calls_per_sec))
+}
+
+// Wait until the next pactl call is allowed; holding the lock while sleeping
+// also spaces out callers on other threads
+fn throttle_pactl() {
+    let mut limiter = PACTL_LIMITER.lock().unwrap();
+    if let Some(last) = limiter.last {
+        let next = last + limiter.min_gap;
+        let now = Instant::now();
+        if next > now {
+            thread::sleep(next - now);
+        }
+    }
+    limiter.last = Some(Instant::now());
+}
+
+// Sinks a notification plays on besides the default sink
+#[derive(Clone, Debug, Default, PartialEq)]
+enum MirrorSinks {
+    #[default]
+    None,
+    // Every sink that is not a monitor
+    All,
+    Listed(Vec<String>),
+}
+
+// Settings resolved from command line, environment and config for the server
+struct ServerSettings {
     fade_out: f32,
     fade_in: f32,
-    volume: u8,
+    volume: f32,
+    // Config volumes keyed by sink name, looked up for the sink each sound plays on
+    sink_volumes: HashMap<String, f32>,
+    poll_interval: Duration,
+    // Interruption window when set explicitly; otherwise each sound's length
+    max_duration: Option<Duration>,
+    // Stay resident for this long with an empty queue instead of exiting immediately
+    idle_timeout: Option<Duration>,
+    // Least time between state-changing pactl calls (--pactl-rate)
+    pactl_gap: Duration,
+    // Save the queue for the next server to replay
+    persist_queue: bool,
+    // Template for the playback command; paplay (or the first other default player found) when unset
+    player_cmd: Option<String>,
+    // Set on the notification stream by the players (PULSE_PROP for a player command)
+    stream_properties: Vec<(String, String)>,
+    // Theme sounds go to canberra-gtk-play instead of the player
+    backend_player: BackendPlayer,
+    // Media roles of the streams to duck; empty ducks every stream
+    duck_roles: Vec<String>,
+    // Log events and received requests to stdout
+    foreground: bool,
+    // Leave all sink and input state untouched
+    no_duck: bool,
+    // Let the playing sound finish on the first interrupt signal
+    no_interrupt_current: bool,
+    // Convert WAV files to the sink's sample rate before playing
+    resample: bool,
+    // Random variation of each play's volume (percentage points) and pitch (percent)
+    jitter_volume: f32,
+    jitter_pitch: f32,
+    // Time-of-day volume caps, checked when each sound plays
+    schedule: Vec<ScheduleRule>,
+    // Attention sound played before the main one, keyed by the alias (or path) as requested
+    prerolls: HashMap<String, PathBuf>,
+    // Resume a suspended sink before the fade-out
+    warmup: bool,
+    // Mute the default source while a notification plays
+    mute_mic: bool,
+    // Fade the sink volume around the sound even without streams to fade
+    fade_sink: bool,
+    // Fade the background partly in and out again between an interrupted sound and the next
+    fade_on_interrupt: bool,
+    // Cap each sound's fades at a share of its length
+    adaptive_fade: bool,
+    // Sinks the sound also plays on
+    mirror_sinks: MirrorSinks,
+    // Leave sinks whose volume the user changed while ducked
+    preserve_user_changes: bool,
+    // Retry unmuting inputs still muted after the restore
+    verify_restore: bool,
+    // Write audio state snapshots around each notification
+    snapshot_dir: Option<PathBuf>,
+    queue_policy: QueuePolicy,
+    // Bound on the waiting requests and what happens beyond it
+    queue_limit: QueueLimit,
+    // Running in the background after --detach forked us
+    detached: bool,
+    // Queue the first sound again at each --every period; the server stays up until it is done
+    interval: Option<PlaySchedule>,
+    // Fade shape from the named ramp profile; linear when unset
+    ramp: Option<Vec<f32>>,
+    // Commands run around each sound's playback
+    pre_hook: Option<String>,
+    post_hook: Option<String>,
+    // Post every event here; like listen, fixed for the server's lifetime
+    #[cfg_attr(not(feature = "webhook"), allow(dead_code))]
+    webhook: Option<WebhookUrl>,
+    // Accept remote requests on this TCP address; the server stays resident while listening
+    listen: Option<SocketAddr>,
+    token: Option<String>,
+}
+
+// Add this struct before the play_notification function
+struct NotificationContext<'a> {
+    sound: QueuedSound,
+    settings: &'a ServerSettings,
     running: &'a Arc<AtomicBool>,
     lock_path: &'a PathBuf,
-    notification_queue: &'a Arc<Mutex<Vec<PathBuf>>>,
+    control: &'a Arc<ControlState>,
+    notification_queue: &'a Arc<Mutex<VecDeque<QueuedSound>>>,
     guard: &'a mut AudioStateGuard,
     enable_fading: bool,
     enable_volume_control: bool,
@@ -358,11 +2421,47 @@ struct NotificationContext<'a> {
 }
 
 fn main() -> Result<()> {
+    run().map_err(with_runtime_dir_hint)
+}
+
+fn run() -> Result<()> {
     // Parse all arguments
-    let args = Args::parse();
+    let mut args = Args::parse();
+    if args.time {
+        start_timing();
+    }
+
+    // Handle config check command; only the given file is read, so a broken default config
+    // doesn't get in the way
+    if let Some(path) = &args.check_config {
+        let path = expand_path(&path.to_string_lossy())?;
+        return check_config_file(&path, args.allow_missing);
+    }
 
     // Load config file if specified or look for default locations
-    let config = load_config(&args.config)?;
+    args.config = args.config
+        .map(|path| expand_path(&path.to_string_lossy()))
+        .transpose()?;
+    if args.no_config {
+        args.config = None;
+    }
+    let config = if args.no_config { Config::default() } else { load_config(&args.config, args.cwd_config)? };
+    mark_phase("config load");
+
+    // Play the alias numbered by --list-sounds
+    if let Some(index) = args.index {
+        args.sound = Some(alias_by_index(&config, index)?);
+    }
+
+    // Let the config pick the sound for a category
+    if let Some(category) = &args.category {
+        args.sound = Some(alias_for_category(&config, category, args.urgent));
+    }
+
+    // Urgent requests without a sound still get one
+    if args.sound.is_none() && args.urgent {
+        args.sound = Some(default_tone(&config, true));
+    }
 
     // Handle help info command
     if args.help_info {
@@ -370,177 +2469,1047 @@ fn main() -> Result<()> {
         return Ok(());
     }
 
+    // Handle config scaffolding command
+    if args.config_init {
+        let path = init_config(&args.config, args.force)?;
+        println!("Wrote starter config to {}", path.display());
+        return Ok(());
+    }
+
     // Handle list sounds command
     if args.list_sounds {
         print_sound_aliases(&config);
         return Ok(());
     }
 
-    // Check if sound is provided
-    let sound = match args.sound {
-        Some(s) => s,
-        None => {
-            eprintln!("Error: No sound specified.");
-            eprintln!("Usage: vh-notification-sound [OPTIONS] <SOUND>");
-            eprintln!("Try 'vh-notification-sound --help' for more information.");
-            return Ok(());
-        }
+    // Determine lock file and control socket paths
+    let lock_path = match &args.runtime_dir {
+        Some(dir) => runtime_dir_lock_path(dir)?,
+        None => default_lock_path(),
     };
+    let socket_path = lock_path.with_extension("sock");
 
-    // Determine parameters with proper precedence: command line > environment > config > defaults
-    // Note: clap automatically reads from environment variables (via env attribute) if CLI arg is not provided
-    // For fade durations: --fade-out/--fade-in override --fade, which overrides config, which has defaults
-    let fade_out = args.fade_out
-        .or(args.fade)
-        .or(config.fade_out)
-        .unwrap_or(0.3);
+    // Handle lock cleanup command
+    if args.clean {
+        clean_stale_locks(&lock_path);
+        return Ok(());
+    }
 
-    let fade_in = args.fade_in
-        .or(args.fade)
-        .or(config.fade_in)
-        .unwrap_or(0.3);
+    // Handle forced shutdown command
+    if args.kill {
+        return kill_server(&lock_path);
+    }
 
-    let volume = args.volume
-        .or(config.volume)
-        .unwrap_or(75)
-        .min(100);
+    // Handle status command
+    if args.status {
+        print_status(&lock_path, &socket_path, args.json);
+        return Ok(());
+    }
 
-    // Resolve sound path (check if it's an alias in config)
-    let sound_path = resolve_sound_path(&sound, &config)?;
+    // Handle speaker check command
+    if args.sweep {
+        return play_sweep(&lock_path);
+    }
 
-    // If detach is enabled, fork the process
-    if args.detach {
-        match unsafe { fork() } {
-            -1 => {
-                return Err(anyhow::anyhow!("Failed to fork process"));
-            }
-            0 => {
-                // Child process continues
-                // Redirect standard file descriptors to /dev/null
-                let null_fd = std::fs::File::open("/dev/null")?.into_raw_fd();
-                unsafe {
-                    dup2(null_fd, STDIN_FILENO);
-                    dup2(null_fd, STDOUT_FILENO);
-                    dup2(null_fd, STDERR_FILENO);
-                    close(null_fd);
-                }
+    // Handle subscribe command
+    if args.subscribe {
+        return subscribe_events(&socket_path);
+    }
 
-                // Create a new session
-                if unsafe { setsid() } < 0 {
-                    std::process::exit(1);
-                }
-            }
-            _ => {
-                // Parent process exits
-                return Ok(());
-            }
+    // Handle queue inspection and per-request control
+    if args.list_queue {
+        match send_control_request(&socket_path, &ControlRequest::List) {
+            Ok(response) if args.json => println!("{}", serde_json::to_string(&response.queue)?),
+            Ok(response) => match &response.queue {
+                Some(listing) => print_queue(listing),
+                None => println!("{}", response.message),
+            },
+            Err(e) => eprintln!("Error communicating with notification server: {:#}", e),
+        }
+        return Ok(());
+    }
+    if let Some(id) = args.cancel {
+        match send_control_request(&socket_path, &ControlRequest::Cancel { id }) {
+            Ok(response) => println!("{}", response.message),
+            Err(e) => eprintln!("Error communicating with notification server: {:#}", e),
+        }
+        return Ok(());
+    }
+
+    if args.reload {
+        match send_control_request(&socket_path, &ControlRequest::Reload) {
+            Ok(response) => println!("{}", response.message),
+            Err(e) => eprintln!("Error communicating with notification server: {:#}", e),
+        }
+        return Ok(());
+    }
+
+    if args.ack {
+        match send_control_request(&socket_path, &ControlRequest::Ack) {
+            Ok(response) => println!("{}", response.message),
+            Err(e) => eprintln!("Error communicating with notification server: {:#}", e),
+        }
+        return Ok(());
+    }
+
+    if args.restore {
+        match send_control_request(&socket_path, &ControlRequest::Restore) {
+            Ok(response) => println!("{}", response.message),
+            Err(e) => eprintln!("Error communicating with notification server: {:#}", e),
+        }
+        return Ok(());
+    }
+
+    // Handle flush commands
+    if args.flush || args.flush_now {
+        match send_control_request(&socket_path, &ControlRequest::Flush { now: args.flush_now }) {
+            Ok(response) => println!("{}", response.message),
+            Err(e) => eprintln!("Error communicating with notification server: {:#}", e),
+        }
+        return Ok(());
+    }
+
+    if args.once_for.is_some_and(|once_for| once_for.as_secs_f32() > MAX_ONCE_FOR) {
+        anyhow::bail!("--once-for can be at most 720h");
+    }
+
+    // Check if sound is provided (saving defaults alone needs none)
+    let sound = match args.sound.as_deref().map(parse_sound_spec).transpose()? {
+        Some(spec) => Some(spec),
+        // A listening server can start with an empty queue, and --duck-while plays nothing
+        None if args.save_defaults || args.listen.is_some() || args.duck_while => None,
+        None => {
+            eprintln!("Error: No sound specified.");
+            eprintln!("Usage: vh-notification-sound [OPTIONS] <SOUND>");
+            eprintln!("Try 'vh-notification-sound --help' for more information.");
+            return Ok(());
+        }
+    };
+
+    // An alias or category gated by an environment variable is skipped while it isn't set; so
+    // is a category whose alias is gated (a --remote sound may name a category)
+    let gated = [args.category.as_deref(), sound.as_ref().map(|spec| spec.sound.as_str())]
+        .into_iter()
+        .flatten()
+        .flat_map(|name| std::iter::once(name).chain(config.categories.get(name).map(String::as_str)))
+        .find_map(|name| closed_gate(&config, name).map(|var| (name, var)));
+    if let Some((name, var)) = gated {
+        println!("{} suppressed by gate {}", name, var);
+        return Ok(());
+    }
+
+    // Remote requests are resolved and played by the remote server
+    if let Some(remote) = &args.remote {
+        let token = args.token.clone().context("--remote requires --token or VH_NOTIFICATION_TOKEN")?;
+        let request = RemoteRequest {
+            token,
+            sound: args.sound.clone().unwrap_or_default(),
+            options: RequestOptions {
+                priority: if args.urgent { Priority::High } else { args.priority.unwrap_or_default() },
+                volume: args.volume.map(|volume| volume.clamp(0.0, 100.0)),
+                relative_volume: args.volume_relative.map(|percent| percent.max(0.0)),
+                urgent: args.urgent,
+                ttl: args.ttl.map(|ttl| ttl.as_secs_f32()),
+                flash: args.flash || args.silent_flash,
+                silent: args.silent_flash,
+                no_repeat: args.no_repeat,
+                once_for: args.once_for.map(|duration| duration.as_secs_f32()),
+                until_ack: args.until_ack,
+                no_restore: args.no_restore,
+            },
+            once: args.once.clone(),
+        };
+        match send_remote_request(remote, &request) {
+            Ok(response) if response.ok => eprintln!("{}", response.message),
+            Ok(response) => eprintln!("Error: {}", response.message),
+            Err(e) => eprintln!("Error communicating with remote notification server: {:#}", e),
+        }
+        return Ok(());
+    }
+
+    let inline = sound.clone().unwrap_or_default();
+    if args.save_defaults {
+        let (fade_out, fade_in) = fade_durations(&args, &inline, &config);
+        // Port overrides are route-specific, so they are not saved as the global volume
+        let default_volume = args.volume.or(inline.volume).or(config.volume).unwrap_or(75.0).clamp(0.0, 100.0);
+        let path = save_defaults(&args.config, args.cwd_config, fade_out, fade_in, default_volume)?;
+        println!("Saved defaults to {}", path.display());
+    }
+    let sound = sound.map(|spec| spec.sound);
+    if sound.is_none() && args.listen.is_none() && !args.duck_while {
+        return Ok(());
+    }
+
+    // Overrides for the active output route (headphones, speakers, ...)
+    let port_override = active_port_override(&config);
+    let mut settings = server_settings(&args, &inline, &config, port_override)?;
+    set_pactl_rate(settings.pactl_gap);
+    if args.duck_while {
+        // Hold the lock like a server while the audio is ducked, so no notification ducks and
+        // restores it underneath the command; clients meanwhile leave requests in the lock file
+        acquire_lock(&lock_path, None).context("Can't duck while a notification server is running")?;
+        let running = Arc::new(AtomicBool::new(true));
+        let ducking = Arc::new(AtomicBool::new(true));
+        {
+            // The command gets the terminal's Ctrl-C as well; the restore waits until it has exited
+            let (running, ducking) = (running.clone(), ducking.clone());
+            ctrlc::set_handler(move || {
+                if ducking.load(Ordering::SeqCst) {
+                    eprintln!("Received interrupt signal, restoring audio once the command exits...");
+                } else {
+                    eprintln!("Received interrupt signal, cleaning up...");
+                    running.store(false, Ordering::SeqCst);
+                }
+            })
+            .expect("Error setting Ctrl-C handler");
+        }
+        let code = match duck_while(&args.command, &settings, &running, &lock_path) {
+            Ok(code) => code,
+            Err(e) => {
+                let _ = std::fs::remove_file(&lock_path);
+                return Err(e);
+            }
+        };
+        ducking.store(false, Ordering::SeqCst);
+
+        // A server takes over the lock to play the requests left there meanwhile
+        let _ = set_lock_state(&lock_path, NotificationState::Exiting);
+        if read_lock_file(&lock_path).is_ok_and(|info| info.new_request.is_some()) {
+            let source = SettingsSource {
+                config_file: (!args.no_config).then(|| find_config_path(&args.config, args.cwd_config)).flatten(),
+                args,
+                inline,
+            };
+            run_notification_server(None, settings, source, &config, running, lock_path, socket_path)?;
+        } else {
+            let _ = std::fs::remove_file(&lock_path);
+        }
+        std::process::exit(code);
+    }
+    let volume = active_sink_volume(&settings);
+    if settings.listen.is_some() && settings.token.is_none() {
+        anyhow::bail!("--listen requires --token or VH_NOTIFICATION_TOKEN");
+    }
+    if let Some(every) = args.every {
+        if sound.is_none() {
+            anyhow::bail!("--every needs a sound to play");
+        }
+        if every.is_zero() {
+            anyhow::bail!("--every must be longer than zero");
+        }
+    }
+
+    let request_options = RequestOptions {
+        priority: if args.urgent { Priority::High } else { args.priority.unwrap_or_default() },
+        volume: args.urgent.then(|| (volume + URGENT_VOLUME_BOOST).min(100.0)),
+        relative_volume: args.volume_relative.map(|percent| percent.max(0.0)),
+        urgent: args.urgent,
+        ttl: args.ttl.map(|ttl| ttl.as_secs_f32()),
+        flash: args.flash || args.silent_flash,
+        silent: args.silent_flash,
+        no_repeat: args.no_repeat,
+        once_for: args.once_for.map(|duration| duration.as_secs_f32()),
+        until_ack: args.until_ack,
+        no_restore: args.no_restore,
+    };
+
+    // Resolve sound path (check if it's an alias in config)
+    let sound_path = sound
+        .as_deref()
+        .map(|sound| resolve_sound_path(scheduled_sound(sound, &config.schedule), &config, port_override))
+        .transpose()?;
+    if let Some(sound_path) = &sound_path {
+        check_sound_dir(sound_path)?;
+    }
+
+    // Simple mode plays once without coordinating with other invocations
+    let mut request = sound_path.map(|path| QueuedSound::new(path, sound, request_options).with_once(args.once.clone()));
+
+    // Without a server to remember earlier picks, choose from a directory right away
+    if let (true, Some(request)) = (args.preview || args.simple, &mut request) {
+        if request.path.is_dir() {
+            request.path = pick_sound_from_dir(&request.path, None)?;
+        }
+    }
+
+    // A detached server claims the lock before forking, so of a burst of -d invocations only
+    // one starts a server and the others queue their requests with it
+    let detach = args.detach && !args.foreground;
+    let mut claim = None;
+    if detach && !args.simple && !args.preview {
+        let claimed = acquire_lock(&lock_path, request.as_ref())?;
+        if claimed.is_some() && settings.interval.is_none() {
+            mark_phase("request sent");
+            eprintln!("Notification request sent to running instance.");
+            print_timings();
+            return Ok(());
+        }
+        claim = Some(claimed);
+    }
+
+    // If detach is enabled, fork the process (--foreground always stays attached)
+    if detach {
+        match unsafe { fork() } {
+            -1 => {
+                return Err(anyhow::anyhow!("Failed to fork process"));
+            }
+            0 => {
+                // Child process continues
+                // Redirect standard file descriptors to /dev/null
+                let null_fd = std::fs::File::open("/dev/null")?.into_raw_fd();
+                unsafe {
+                    dup2(null_fd, STDIN_FILENO);
+                    dup2(null_fd, STDOUT_FILENO);
+                    dup2(null_fd, STDERR_FILENO);
+                    close(null_fd);
+                }
+
+                // Create a new session
+                if unsafe { setsid() } < 0 {
+                    std::process::exit(1);
+                }
+            }
+            child => {
+                // Parent process exits, once the child has taken over a lock we claimed
+                if let Some(None) = claim {
+                    wait_for_lock_handover(&lock_path, child as u32);
+                }
+                return Ok(());
+            }
         }
     }
 
     // Set up signal handling for clean shutdown
     let running = Arc::new(AtomicBool::new(true));
     let r = running.clone();
+    let no_interrupt_current = settings.no_interrupt_current;
 
     ctrlc::set_handler(move || {
-        eprintln!("Received interrupt signal, cleaning up...");
+        if no_interrupt_current && SHUTDOWN_SIGNALS.fetch_add(1, Ordering::SeqCst) == 0 {
+            eprintln!("Received interrupt signal, finishing the current sound (interrupt again to stop it)...");
+        } else {
+            SHUTDOWN_SIGNALS.fetch_add(1, Ordering::SeqCst);
+            eprintln!("Received interrupt signal, cleaning up...");
+        }
         r.store(false, Ordering::SeqCst);
     })
     .expect("Error setting Ctrl-C handler");
 
-    // Determine lock file path
-    let lock_path = dirs::runtime_dir()
-        .unwrap_or_else(|| PathBuf::from("/tmp"))
-        .join("vh-notification-sound.lock");
+    if let (true, Some(request)) = (args.preview, &request) {
+        return play_preview(&request.path, &request_options, &settings);
+    }
+
+    if let (true, Some(request)) = (args.simple, &request) {
+        let result = play_simple(request, &settings, &running, &lock_path);
+        print_timings();
+        return result;
+    }
 
-    // Try to acquire lock or send request to existing server
-    match acquire_lock(&lock_path, &sound_path.to_string_lossy()) {
-        Ok(None) => {
-            // No existing notification server, start a new one
-            run_notification_server(sound_path, fade_out, fade_in, volume, running, lock_path)?;
+    // Try to acquire lock or send request to existing server. With --every the request is sent
+    // again each period while another server plays it; a server we start takes over the rest.
+    let mut schedule = settings.interval;
+    loop {
+        let attempt = match claim.take() {
+            Some(claimed) => Ok(claimed),
+            None => acquire_lock(&lock_path, request.as_ref()),
+        };
+        match attempt {
+            Ok(None) => {
+                // No existing notification server, start a new one
+                settings.interval = schedule;
+                let source = SettingsSource {
+                    config_file: (!args.no_config).then(|| find_config_path(&args.config, args.cwd_config)).flatten(),
+                    args,
+                    inline,
+                };
+                run_notification_server(request, settings, source, &config, running, lock_path, socket_path)?;
+                break;
+            }
+            Ok(Some(_)) => {
+                // Successfully communicated with existing process
+                mark_phase("request sent");
+                eprintln!("Notification request sent to running instance.");
+            }
+            // The lock file itself can't be written, which retrying won't fix
+            Err(e) if e.downcast_ref::<RuntimePathError>().is_some() => return Err(e),
+            Err(e) => {
+                eprintln!("Error communicating with notification server: {}", e);
+            }
         }
-        Ok(Some(_)) => {
-            // Successfully communicated with existing process
-            eprintln!("Notification request sent to running instance.");
+
+        if !schedule.as_mut().is_some_and(|schedule| schedule.wait(&running, settings.poll_interval)) {
+            break;
         }
+    }
+
+    print_timings();
+    Ok(())
+}
+
+// Fade durations with proper precedence: command line > environment > inline spec > config > defaults.
+// Note: clap automatically reads from environment variables (via env attribute) if CLI arg is not provided.
+// --fade-out/--fade-in override --fade, which overrides config, which has defaults
+fn fade_durations(args: &Args, inline: &SoundSpec, config: &Config) -> (f32, f32) {
+    let fade_out = args.fade_out
+        .or(args.fade)
+        .or(inline.fade_out)
+        .or(inline.fade)
+        .or(config.fade_out)
+        .unwrap_or(0.3);
+
+    let fade_in = args.fade_in
+        .or(args.fade)
+        .or(inline.fade_in)
+        .or(inline.fade)
+        .or(config.fade_in)
+        .unwrap_or(0.3);
+
+    (fade_out, fade_in)
+}
+
+// Resolve the server settings from the command line, the inline spec of the first sound and
+// the config; called again with the new config when the server reloads
+fn server_settings(
+    args: &Args,
+    inline: &SoundSpec,
+    config: &Config,
+    port_override: Option<&PortOverride>,
+) -> Result<ServerSettings> {
+    let (fade_out, fade_in) = fade_durations(args, inline, config);
+
+    let explicit_volume = args.volume
+        .or(inline.volume)
+        .or(port_override.and_then(|o| o.volume));
+    let volume = explicit_volume
+        .or(config.volume)
+        .unwrap_or(75.0)
+        .clamp(0.0, 100.0);
+    // A volume given on the command line, inline or for the port wins over the per-sink ones
+    let sink_volumes = match explicit_volume {
+        Some(_) => HashMap::new(),
+        None => config.sink_volumes.iter().map(|(sink, volume)| (sink.clone(), volume.clamp(0.0, 100.0))).collect(),
+    };
+
+    let poll_interval = args.poll_interval
+        .or(config.poll_interval)
+        .unwrap_or(DEFAULT_POLL_INTERVAL);
+    let poll_interval = seconds_setting("poll_interval", poll_interval, 0.001, MAX_POLL_INTERVAL)?;
+
+    let max_duration = args.max_duration
+        .or(config.max_duration)
+        .map(|max_duration| seconds_setting("max_duration", max_duration, 0.0, MAX_WAIT_SETTING))
+        .transpose()?;
+
+    let idle_timeout = args.idle_timeout
+        .or(config.idle_timeout)
+        .map(|timeout| seconds_setting("idle_timeout", timeout, 0.0, MAX_WAIT_SETTING))
+        .transpose()?
+        .filter(|timeout| !timeout.is_zero());
+
+    let pactl_gap = pactl_gap(args.pactl_rate.or(config.pactl_rate).unwrap_or(DEFAULT_PACTL_RATE))?;
+
+    Ok(ServerSettings {
+        fade_out,
+        fade_in,
+        volume,
+        sink_volumes,
+        poll_interval,
+        max_duration,
+        idle_timeout,
+        pactl_gap,
+        persist_queue: args.persist_queue || config.persist_queue.unwrap_or(false),
+        player_cmd: args.player_cmd.clone().or(config.player_cmd.clone()),
+        stream_properties: stream_properties(args, config),
+        backend_player: args.backend_player.or(config.backend_player).unwrap_or_default(),
+        duck_roles: if args.duck_roles.is_empty() {
+            config.duck_roles.clone()
+        } else {
+            args.duck_roles.clone()
+        },
+        foreground: args.foreground,
+        no_duck: args.no_duck,
+        no_interrupt_current: args.no_interrupt_current,
+        resample: args.resample || config.resample.unwrap_or(false),
+        jitter_volume: args.jitter_volume.or(config.jitter_volume).unwrap_or(0.0).clamp(0.0, 100.0),
+        jitter_pitch: args.jitter_pitch.or(config.jitter_pitch).unwrap_or(0.0).clamp(0.0, MAX_JITTER_PITCH),
+        schedule: config.schedule.clone(),
+        // check-config reports prerolls that lead nowhere; here they are just left out
+        prerolls: config
+            .preroll
+            .iter()
+            .filter(|(_, preroll)| sound_reference_resolves(config, preroll))
+            .filter_map(|(alias, preroll)| Some((alias.clone(), resolve_sound_path(preroll, config, port_override).ok()?)))
+            .collect(),
+        warmup: args.warmup || config.warmup.unwrap_or(false),
+        mute_mic: args.mute_mic || config.mute_mic.unwrap_or(false),
+        fade_sink: args.fade_sink || config.fade_sink.unwrap_or(false),
+        fade_on_interrupt: !args.no_fade_on_interrupt
+            && (args.fade_on_interrupt || config.fade_on_interrupt.unwrap_or(false)),
+        adaptive_fade: args.adaptive_fade || config.adaptive_fade.unwrap_or(false),
+        mirror_sinks: if args.sink_all {
+            MirrorSinks::All
+        } else if !args.sinks.is_empty() {
+            MirrorSinks::Listed(args.sinks.clone())
+        } else if config.sink_all.unwrap_or(false) {
+            MirrorSinks::All
+        } else if !config.sinks.is_empty() {
+            MirrorSinks::Listed(config.sinks.clone())
+        } else {
+            MirrorSinks::None
+        },
+        preserve_user_changes: args.preserve_user_changes || config.preserve_user_changes.unwrap_or(false),
+        verify_restore: args.verify_restore || config.verify_restore.unwrap_or(false),
+        snapshot_dir: args.snapshot.clone(),
+        queue_policy: args.queue_policy.or(config.queue_policy).unwrap_or_default(),
+        queue_limit: queue_limit(args, config, port_override),
+        detached: args.detach && !args.foreground,
+        interval: args.every.map(|every| PlaySchedule::new(every, args.count)),
+        ramp: args.ramp.clone().or(config.ramp.clone()).map(|name| resolve_ramp(config, &name)).transpose()?,
+        pre_hook: args.pre_hook.clone().or(config.pre_play.clone()),
+        post_hook: args.post_hook.clone().or(config.post_play.clone()),
+        webhook: args.webhook.as_deref().or(config.webhook.as_deref()).map(parse_webhook_url).transpose()?,
+        listen: args.listen.as_deref().map(parse_listen_addr).transpose()?,
+        token: args.token.clone(),
+    })
+}
+
+// The defaults, overridden by the config and then --stream-property; empty values are dropped
+fn stream_properties(args: &Args, config: &Config) -> Vec<(String, String)> {
+    let mut properties: Vec<(String, String)> = DEFAULT_STREAM_PROPERTIES
+        .iter()
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect();
+    let mut configured: Vec<_> = config.stream_properties.iter().map(|(key, value)| (key.clone(), value.clone())).collect();
+    configured.sort();
+    configured.retain(|(key, value)| match check_stream_property(key, value) {
+        Ok(()) => true,
         Err(e) => {
-            eprintln!("Error communicating with notification server: {}", e);
+            eprintln!("Warning: Ignoring stream property {} from the config: {}", key, e);
+            false
         }
+    });
+    for (key, value) in configured.into_iter().chain(args.stream_properties.iter().cloned()) {
+        properties.retain(|(existing, _)| *existing != key);
+        properties.push((key, value));
+    }
+    properties.retain(|(_, value)| !value.is_empty());
+    properties
+}
+
+// A setting in seconds as a Duration, raised to at least min; from_secs_f32 would panic on NaN,
+// infinity or an overflow
+fn seconds_setting(name: &str, seconds: f32, min: f32, max: f32) -> Result<Duration> {
+    if !seconds.is_finite() || seconds > max {
+        anyhow::bail!("{} must be a number of seconds up to {}, not {}", name, max, seconds);
     }
+    Ok(Duration::from_secs_f32(seconds.max(min)))
+}
 
-    Ok(())
+// --max-queue and --overflow over the config; the overflow sound is only resolved when it can play
+fn queue_limit(args: &Args, config: &Config, port_override: Option<&PortOverride>) -> QueueLimit {
+    let overflow = args.overflow.or(config.overflow).unwrap_or_default();
+    let sound = (overflow == OverflowPolicy::Sound)
+        .then(|| config.overflow_sound.as_deref().unwrap_or(DEFAULT_OVERFLOW_SOUND))
+        .filter(|sound| sound_reference_resolves(config, sound))
+        .and_then(|sound| resolve_sound_path(sound, config, port_override).ok());
+    QueueLimit {
+        max: args.max_queue.or(config.max_queue).unwrap_or(DEFAULT_MAX_QUEUE),
+        overflow,
+        sound,
+    }
+}
+
+// What a reload needs to rebuild the server settings, so the command line keeps its precedence
+struct SettingsSource {
+    args: Args,
+    inline: SoundSpec,
+    // The config file the server started with, if any
+    config_file: Option<PathBuf>,
+}
+
+// Re-read the config the server started with. A file that has gone missing or is empty is most
+// likely being edited, so that is an error rather than a reset to the defaults.
+fn reload_config(source: &SettingsSource) -> Result<Config> {
+    if source.args.no_config {
+        return Ok(Config::default());
+    }
+    let Some(path) = &source.config_file else {
+        return load_config(&source.args.config, source.args.cwd_config);
+    };
+    let yaml = std::fs::read_to_string(path).context(format!("Failed to read config file {}", path.display()))?;
+    if yaml.trim().is_empty() {
+        anyhow::bail!("Config file {} is empty", path.display());
+    }
+    serde_yaml::from_str(&yaml).context(format!("Failed to parse config file {}", path.display()))
+}
+
+// Set by SIGHUP (or a reload control request); the server reloads before its next sound
+static RELOAD_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn request_reload(_signal: libc::c_int) {
+    RELOAD_REQUESTED.store(true, Ordering::SeqCst);
+}
+
+// Set by SIGUSR1; the main loop, the fade steps and the playback monitor write a StateDump
+// the next time they come round
+static DUMP_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn request_dump(_signal: libc::c_int) {
+    DUMP_REQUESTED.store(true, Ordering::SeqCst);
+}
+
+// What the server thinks is going on, for debugging a server stuck ducked without stopping it
+#[derive(Debug, Serialize)]
+struct StateDump {
+    pid: u32,
+    // State recorded in the lock file
+    state: Option<NotificationState>,
+    fade_state: u8,
+    ducked: bool,
+    audio_already_prepared: bool,
+    default_sink: String,
+    // Volume restored after ducking, and the other sinks faded with their original volumes
+    current_volume: f32,
+    duck_sinks: Vec<(String, f32)>,
+    volume_settable: bool,
+    unmuted_inputs: Vec<String>,
+    muted_source: Option<String>,
+    needs_restore_volume: bool,
+    needs_unmute_inputs: bool,
+    queue: Option<QueueListing>,
+}
+
+// What a dump written from inside the guard needs besides the guard itself
+struct DumpTarget {
+    control: Arc<ControlState>,
+    lock_path: PathBuf,
+    detached: bool,
+    // As for the notification being played
+    audio_already_prepared: bool,
+}
+
+// A detached server has no stderr, so its dump goes to a file next to the lock file
+fn write_state_dump(dump: &mut StateDump, control: &ControlState, lock_path: &PathBuf, detached: bool) {
+    dump.state = read_lock_file(lock_path).ok().map(|info| info.state);
+    dump.queue = list_queue(control).queue;
+    let json = match serde_json::to_string_pretty(dump) {
+        Ok(json) => json,
+        Err(e) => {
+            eprintln!("Warning: Failed to serialize state dump: {}", e);
+            return;
+        }
+    };
+    if !detached {
+        eprintln!("State dump:\n{}", json);
+        return;
+    }
+    let path = lock_path.with_extension("dump.json");
+    if let Err(e) = std::fs::write(&path, json + "\n") {
+        eprintln!("Warning: Failed to write state dump {}: {}", path.display(), e);
+    }
 }
 
 fn run_notification_server(
-    initial_sound: PathBuf,
-    fade_out: f32,
-    fade_in: f32,
-    volume: u8,
+    initial_sound: Option<QueuedSound>,
+    mut settings: ServerSettings,
+    source: SettingsSource,
+    config: &Config,
     running: Arc<AtomicBool>,
     lock_path: PathBuf,
+    socket_path: PathBuf,
 ) -> Result<()> {
-    // Notification queue
-    let notification_queue = Arc::new(Mutex::new(vec![initial_sound]));
+    // The lock file is ours from here on, and goes away with us even on an error
+    let _server_files = ServerFiles {
+        lock_path: lock_path.clone(),
+        socket_path: socket_path.clone(),
+    };
+
+    // Server state shared with the control socket and the intake threads
+    let notification_queue = Arc::new(Mutex::new(VecDeque::new()));
+    let control = Arc::new(ControlState {
+        events: EventBus {
+            log: settings.foreground,
+            #[cfg(feature = "webhook")]
+            webhook: settings.webhook.clone().map(Webhook::start),
+            ..Default::default()
+        },
+        notification_queue: notification_queue.clone(),
+        current: Mutex::new(None),
+        once_keys: Mutex::new(HashMap::new()),
+        queue_limit: Mutex::new(Some(settings.queue_limit.clone())),
+        queue_file: Mutex::new(None),
+        skip_current: AtomicBool::new(false),
+        playing: Mutex::new(None),
+        lock_path: lock_path.clone(),
+        exiting: AtomicBool::new(false),
+        attention: Mutex::new(None),
+        hold_until: Mutex::new(None),
+    });
+
+    // The queue starts with what a previous server left unplayed
+    if settings.persist_queue {
+        let queue_file = queue_file_path(&lock_path);
+        for sound in load_saved_queue(&queue_file) {
+            if settings.foreground {
+                println!("restored: {}", sound.path.display());
+            }
+            enqueue(&control, sound);
+        }
+        *control.queue_file.lock().unwrap() = Some(queue_file);
+        save_queue(&control, &notification_queue.lock().unwrap());
+    }
+    // The first request again for each --every period
+    let scheduled_sound = initial_sound.as_ref().map(|sound| (sound.path.clone(), sound.alias.clone(), sound.options));
+    if let Some(sound) = initial_sound {
+        enqueue(&control, sound);
+    }
 
-    // Initialize the lock file with our PID and initial state
+    // Initialize the lock file with our PID and initial state, keeping a request a client wrote
+    // into the lock we claimed before our control socket was up
+    let pending = read_lock_file(&lock_path).ok().filter(|info| info.new_request.is_some());
     let lock_info = LockInfo {
         pid: std::process::id(),
+        protocol: PROTOCOL_VERSION,
         state: NotificationState::Idle,
-        new_request: None,
+        new_request: pending.as_ref().and_then(|info| info.new_request.clone()),
+        request_pid: pending.as_ref().and_then(|info| info.request_pid),
+        request_alias: pending.as_ref().and_then(|info| info.request_alias.clone()),
+        request_options: pending.as_ref().map(|info| info.request_options).unwrap_or_default(),
+        request_once: pending.as_ref().and_then(|info| info.request_once.clone()),
+        fade_progress: None,
+        last_pick: None,
+        muted_source: None,
+        ducked_audio: None,
     };
 
     update_lock_file(&lock_path, &lock_info)?;
 
+    // Serve the control socket for event subscribers and queue commands
+    if let Err(e) = start_control_socket(&socket_path, settings.poll_interval, running.clone(), control.clone()) {
+        eprintln!("Warning: Failed to start control socket: {}", e);
+    }
+
+    // Accept requests from other hosts when asked to listen on TCP
+    let remote = match (settings.listen, &settings.token) {
+        (Some(addr), Some(token)) => {
+            let remote = Arc::new(RemoteListener {
+                settings: Mutex::new(RemoteSettings {
+                    token: token.clone(),
+                    config: config.clone(),
+                    volume: settings.volume,
+                }),
+                foreground: settings.foreground,
+                control: control.clone(),
+            });
+            start_remote_listener(addr, settings.poll_interval, running.clone(), remote.clone())?;
+            Some(remote)
+        }
+        _ => None,
+    };
+
     // Create a thread to check for new notification requests
     let lock_path_clone = lock_path.clone();
     let running_clone = running.clone();
-    let queue_clone = notification_queue.clone();
+    let control_clone = control.clone();
+    let check_interval = settings.poll_interval;
+    let idle_timeout = settings.idle_timeout;
+    // When the server went idle with an empty queue; None while busy
+    let idle_since: Arc<Mutex<Option<Instant>>> = Arc::new(Mutex::new(None));
+    let idle_since_clone = idle_since.clone();
+    let foreground = settings.foreground;
+
+    // Plays still to come from --every keep the server running
+    let schedule_pending = Arc::new(AtomicBool::new(false));
+    if let (Some(mut schedule), Some((path, alias, options))) = (settings.interval, scheduled_sound) {
+        schedule_pending.store(!schedule.done(), Ordering::SeqCst);
+        let running = running.clone();
+        let control = control.clone();
+        let idle_since = idle_since.clone();
+        let pending = schedule_pending.clone();
+        thread::spawn(move || {
+            while schedule.wait(&running, check_interval) {
+                if foreground {
+                    println!("scheduled: {}", path.display());
+                }
+                *idle_since.lock().unwrap() = None;
+                enqueue(&control, QueuedSound::new(path.clone(), alias.clone(), options));
+                pending.store(!schedule.done(), Ordering::SeqCst);
+            }
+            pending.store(false, Ordering::SeqCst);
+        });
+    }
+    let schedule_pending_clone = schedule_pending.clone();
 
     thread::spawn(move || {
-        let check_interval = Duration::from_millis(10);
         while running_clone.load(Ordering::SeqCst) {
             // Check for new notification requests in the lock file
             if let Ok(lock_info) = read_lock_file(&lock_path_clone) {
                 if let Some(new_sound_path) = lock_info.new_request {
+                    if foreground {
+                        match lock_info.request_pid {
+                            Some(pid) => println!("request: {} from PID {}", new_sound_path, pid),
+                            None => println!("request: {}", new_sound_path),
+                        }
+                    }
+
+                    // Any request resets the idle timer
+                    *idle_since_clone.lock().unwrap() = None;
+
                     // Add new sound to queue
-                    let mut queue = queue_clone.lock().unwrap();
-                    queue.push(PathBuf::from(&new_sound_path));
+                    if let Err(e) = lock_info.request_options.validate() {
+                        eprintln!("Warning: Dropped request {}: {:#}", new_sound_path, e);
+                    } else {
+                        let sound = QueuedSound::new(
+                            PathBuf::from(&new_sound_path),
+                            lock_info.request_alias,
+                            lock_info.request_options,
+                        )
+                        .with_once(lock_info.request_once);
+                        match enqueue(&control_clone, sound) {
+                            Enqueued::Duplicate if foreground => println!("dropped: {} (already played once)", new_sound_path),
+                            Enqueued::Overflow if foreground => println!("dropped: {} (queue full)", new_sound_path),
+                            _ => {}
+                        }
+                    }
 
                     // Clear the request from the lock file
                     if let Ok(mut updated_info) = read_lock_file(&lock_path_clone) {
                         updated_info.new_request = None;
+                        updated_info.request_pid = None;
+                        updated_info.request_alias = None;
+                        updated_info.request_options = RequestOptions::default();
+                        updated_info.request_once = None;
                         let _ = update_lock_file(&lock_path_clone, &updated_info);
                     }
                 }
             }
+
+            // Stop an idle server once the idle timeout has passed
+            if let Some(timeout) = idle_timeout {
+                let idle_since = *idle_since_clone.lock().unwrap();
+                // A request queued over the control socket doesn't touch the idle timer
+                let queue_empty = control_clone.notification_queue.lock().unwrap().is_empty() && !schedule_pending_clone.load(Ordering::SeqCst);
+                if queue_empty && idle_since.is_some_and(|since| since.elapsed() >= timeout) {
+                    running_clone.store(false, Ordering::SeqCst);
+                }
+            }
+
             thread::sleep(check_interval);
         }
     });
 
     // Get initial PulseAudio state once for the entire server
-    let state = get_pulseaudio_state()?;
+    let state = get_pulseaudio_state(&settings.duck_roles, &settings.mirror_sinks)?;
+    mark_phase("sink probe");
     let mut guard = AudioStateGuard::new(state);
-    let enable_fading = !guard.unmuted_inputs.is_empty() && fade_in >= 0.0 && fade_out >= 0.0;
-    // Control volume when it's safe:
-    // - no active inputs, or
-    // - active inputs are being faded/muted during notification playback.
-    let enable_volume_control = guard.unmuted_inputs.is_empty() || enable_fading;
+    guard.lock_path = Some(lock_path.clone());
+    guard.dump_target = Some(DumpTarget {
+        control: control.clone(),
+        lock_path: lock_path.clone(),
+        detached: settings.detached,
+        audio_already_prepared: false,
+    });
+    guard.ramp = settings.ramp.clone();
+    guard.mute_mic = settings.mute_mic;
+    guard.fade_sink = settings.fade_sink;
+    guard.preserve_user_changes = settings.preserve_user_changes;
+    guard.verify_restore = settings.verify_restore;
+    guard.snapshot_dir = settings.snapshot_dir.clone();
+
+    // Make a detached server recognisable in top
+    if settings.detached {
+        set_process_title(SERVER_PROCESS_TITLE);
+    }
+    let (mut enable_fading, mut enable_volume_control) = ducking_modes(&guard, &settings);
 
-    // Track whether audio is already prepared for notifications
-    // Audio is considered prepared when fade_state is close to 0 (faded out)
+    // Track whether audio is already prepared (faded out and muted) for notifications
     let mut audio_already_prepared = false;
+    // The state captured above is fresh for the first notification only
+    let mut state_is_fresh = true;
+
+    // SIGHUP reloads the config instead of terminating the server; SIGUSR1 dumps its state.
+    // The handlers only set flags, everything else happens in the loop below.
+    unsafe {
+        libc::signal(libc::SIGHUP, request_reload as extern "C" fn(libc::c_int) as libc::sighandler_t);
+        libc::signal(libc::SIGUSR1, request_dump as extern "C" fn(libc::c_int) as libc::sighandler_t);
+    }
 
     // Main notification playback loop
     while running.load(Ordering::SeqCst) {
-        // Get next notification from queue
+        if DUMP_REQUESTED.swap(false, Ordering::SeqCst) {
+            write_state_dump(&mut guard.state_dump(audio_already_prepared), &control, &lock_path, settings.detached);
+        }
+
+        // Reload between sounds; the new config only replaces the current settings once it
+        // has loaded and resolved completely, so a half-written file changes nothing
+        if RELOAD_REQUESTED.swap(false, Ordering::SeqCst) {
+            let reloaded = reload_config(&source).and_then(|config| {
+                let settings = server_settings(&source.args, &source.inline, &config, active_port_override(&config))?;
+                Ok((settings, config))
+            });
+            match reloaded {
+                Ok((new_settings, new_config)) => {
+                    // The intake thread keeps polling with the settings it started with
+                    settings = ServerSettings {
+                        poll_interval: settings.poll_interval,
+                        idle_timeout: settings.idle_timeout,
+                        interval: settings.interval,
+                        ..new_settings
+                    };
+                    set_pactl_rate(settings.pactl_gap);
+                    guard.ramp = settings.ramp.clone();
+                    guard.mute_mic = settings.mute_mic;
+                    guard.fade_sink = settings.fade_sink;
+                    guard.preserve_user_changes = settings.preserve_user_changes;
+                    guard.verify_restore = settings.verify_restore;
+                    *control.queue_limit.lock().unwrap() = Some(settings.queue_limit.clone());
+                    // Remote requests are resolved with the new config; the listening socket
+                    // itself stays bound to the address the server started with
+                    if let Some(remote) = &remote {
+                        let mut remote = remote.settings.lock().unwrap();
+                        if let Some(token) = &settings.token {
+                            remote.token = token.clone();
+                        }
+                        remote.config = new_config;
+                        remote.volume = settings.volume;
+                    }
+                    control.events.emit(&Event::Reloaded);
+                }
+                Err(e) => eprintln!("Warning: Failed to reload config, keeping the current settings: {:#}", e),
+            }
+        }
+
+        // Audio held ducked by --no-restore fades back in once the hold has run out or was
+        // released with --restore, unless the next sound is already waiting
+        let hold_over = {
+            let mut hold_until = control.hold_until.lock().unwrap();
+            let over = hold_until.is_some_and(|until| Instant::now() >= until);
+            if over {
+                *hold_until = None;
+            }
+            over
+        };
+        if hold_over && audio_already_prepared && !has_pending(&notification_queue.lock().unwrap()) {
+            set_lock_state(&lock_path, NotificationState::FadingIn)?;
+            guard.restore_after_notification(settings.fade_in, enable_fading, &running, &|| {
+                has_pending(&notification_queue.lock().unwrap())
+            })?;
+            audio_already_prepared = false;
+            if guard.fade_state == FADE_STEPS {
+                set_lock_state(&lock_path, NotificationState::Idle)?;
+                control.events.emit(&Event::Idle);
+            }
+        }
+
+        // Get next notification from queue: a request of the highest waiting priority,
+        // picked and the rest kept or dropped according to the queue policy
         let sound_to_play = {
             let mut queue = notification_queue.lock().unwrap();
-            if let Some(sound) = queue.pop() {
-                queue.clear();
+            // Drop requests whose TTL ran out while they waited
+            queue.retain(|queued| {
+                let expired = queued.is_expired();
+                if expired {
+                    if settings.foreground {
+                        println!("expired: {}", queued.path.display());
+                    }
+                    forget_once_keys(&control, [queued]);
+                }
+                !expired
+            });
+            let priority = queue.iter().map(|queued| queued.options.priority).max();
+            let next = priority.and_then(|priority| match settings.queue_policy {
+                QueuePolicy::Fifo => queue.iter().position(|queued| queued.options.priority == priority),
+                QueuePolicy::Latest | QueuePolicy::Lifo => {
+                    queue.iter().rposition(|queued| queued.options.priority == priority)
+                }
+            });
+            if let Some(sound) = next.and_then(|index| queue.remove(index)) {
+                if settings.queue_policy == QueuePolicy::Latest {
+                    forget_once_keys(&control, queue.iter());
+                    queue.clear();
+                }
+                // The sound the hold was kept for has arrived
+                control.hold_until.lock().unwrap().take();
+                // Saved as current until it has played
+                *control.current.lock().unwrap() = Some(sound.clone());
+                // A new alert replaces the one waiting for --ack
+                if sound.options.until_ack {
+                    *control.attention.lock().unwrap() = Some(sound.clone());
+                }
+                save_queue(&control, &queue);
+                sound
+            } else if let Some(sound) = control.attention.lock().unwrap().clone() {
+                // Nothing else to play: the alert goes again until acknowledged
+                if settings.foreground {
+                    println!("repeat: {} (until --ack)", sound.path.display());
+                }
                 sound
+            } else if control.hold_until.lock().unwrap().is_some() {
+                // Held ducked for the next sound of a --no-restore script
+                drop(queue);
+                thread::sleep(settings.poll_interval);
+                continue;
+            } else if settings.idle_timeout.is_some() || settings.listen.is_some() || schedule_pending.load(Ordering::SeqCst) {
+                // Stay resident; the intake thread stops us once the idle timeout passes
+                drop(queue);
+                if audio_already_prepared || guard.fade_state != FADE_STEPS {
+                    // Still ducked (or mid-fade) for requests that have since expired
+                    guard.cleanup_or_warn();
+                    guard.fade_state = FADE_STEPS;
+                    audio_already_prepared = false;
+                    control.events.emit(&Event::Idle);
+                }
+                idle_since.lock().unwrap().get_or_insert_with(Instant::now);
+                thread::sleep(settings.poll_interval);
+                continue;
             } else {
-                break; // No more notifications to play, exit loop
+                // No more notifications to play: stop taking requests before restoring the
+                // audio, so clients start a new server instead of queuing into this one
+                control.exiting.store(true, Ordering::SeqCst);
+                drop(queue);
+                set_lock_state(&lock_path, NotificationState::Exiting)?;
+                guard.cleanup_or_warn();
+                guard.fade_state = FADE_STEPS;
+
+                // A request that raced the shutdown is still played here rather than dropped.
+                // The lock file is checked first: the intake thread queues a request before
+                // clearing it there.
+                let request_in_lock = read_lock_file(&lock_path).is_ok_and(|info| info.new_request.is_some());
+                if request_in_lock || has_pending(&notification_queue.lock().unwrap()) {
+                    control.exiting.store(false, Ordering::SeqCst);
+                    set_lock_state(&lock_path, NotificationState::Idle)?;
+                    audio_already_prepared = false;
+                    continue;
+                }
+                break;
             }
         };
+        *idle_since.lock().unwrap() = None;
+
+        // Re-resolve the default sink for each notification so a queue spanning a
+        // device switch (e.g. Bluetooth reconnect) targets the current sink
+        if !state_is_fresh {
+            if audio_already_prepared {
+                // Still ducked: only start over if the sink went away underneath us
+                if ducked_default_sink().is_ok_and(|sink| sink != guard.default_sink) {
+                    if let Err(e) = guard.cleanup() {
+                        eprintln!("Warning: Failed to restore previous sink: {}", e);
+                    }
+                    audio_already_prepared = false;
+                }
+            }
+            // Mid-fade the sink volume is not the one to restore, so keep the captured state
+            if !audio_already_prepared && guard.fade_state == FADE_STEPS {
+                match get_pulseaudio_state(&settings.duck_roles, &settings.mirror_sinks) {
+                    Ok(state) => {
+                        guard.refresh(state);
+                        (enable_fading, enable_volume_control) = ducking_modes(&guard, &settings);
+                    }
+                    Err(e) => eprintln!("Warning: Failed to refresh audio state: {}", e),
+                }
+            }
+        }
+        state_is_fresh = false;
 
         // Update lock file state
         if let Ok(mut lock_info) = read_lock_file(&lock_path) {
@@ -550,12 +3519,11 @@ fn run_notification_server(
 
         // Play the notification sound
         let ctx = &mut NotificationContext {
-            sound_path: sound_to_play,
-            fade_out,
-            fade_in,
-            volume,
+            sound: sound_to_play,
+            settings: &settings,
             running: &running,
             lock_path: &lock_path,
+            control: &control,
             notification_queue: &notification_queue,
             guard: &mut guard,
             enable_fading,
@@ -563,68 +3531,424 @@ fn run_notification_server(
             audio_already_prepared,
         };
 
+        if let Some(target) = &mut ctx.guard.dump_target {
+            target.audio_already_prepared = audio_already_prepared;
+        }
         let (completed, interrupted) = play_notification(ctx)?;
+        {
+            // Played (or cut off): nothing of it is left to save but a pending --ack
+            let queue = notification_queue.lock().unwrap();
+            control.current.lock().unwrap().take();
+            save_queue(&control, &queue);
+        }
 
         // Update the audio preparation state for the next notification
         if interrupted {
-            // If this notification was interrupted, audio is already prepared for the next one
-            // Audio is considered prepared when fade_state is close to 0 (faded out)
-            audio_already_prepared = guard.fade_state < FADE_STEPS / 2;
+            // Interrupted while playing, audio is still prepared for the next one; interrupted
+            // during the fade-in, the next one fades out again from where it stopped
+            audio_already_prepared = guard.ducked;
         } else if completed {
             // If the notification played completely with fade-in, audio should be restored
             // Audio is considered not prepared when fade_state is close to FADE_STEPS (full volume)
             audio_already_prepared = false;
         }
 
-        // Check if we're done with all notifications
-        let no_more_notifications = notification_queue.lock().unwrap().is_empty();
+        // Check if we're done with all notifications; an alert waiting for --ack is not done
+        let no_more_notifications = !has_pending(&notification_queue.lock().unwrap())
+            && control.attention.lock().unwrap().is_none()
+            && control.hold_until.lock().unwrap().is_none();
 
         // If we're done (or shutting down) and audio was not fully restored, do it now
         if (no_more_notifications || !running.load(Ordering::SeqCst)) && (interrupted || !completed)
         {
             // Ensure audio state is fully restored
-            guard.cleanup()?;
+            guard.cleanup_or_warn();
             audio_already_prepared = false;
             guard.fade_state = FADE_STEPS; // Reset fade state to full volume
+            control.events.emit(&Event::Idle);
         }
     }
 
-    // Ensure audio state is fully restored before exiting
-    guard.cleanup()?;
+    // Ensure audio state is fully restored before exiting; the lock file, control socket and
+    // last pitched copy are removed on return whether or not that worked
+    control.exiting.store(true, Ordering::SeqCst);
+    let _ = set_lock_state(&lock_path, NotificationState::Exiting);
+    guard.cleanup_or_warn();
     guard.fade_state = FADE_STEPS; // Reset fade state to full volume
 
-    // Clean up lock file before exiting
-    let _ = std::fs::remove_file(&lock_path);
+    #[cfg(feature = "webhook")]
+    if let Some(webhook) = &control.events.webhook {
+        webhook.wait_sent(WEBHOOK_TIMEOUT);
+    }
 
     Ok(())
 }
 
-// Refactored play_notification function
-fn play_notification(ctx: &mut NotificationContext) -> Result<(bool, bool)> {
-    // Track whether playback was interrupted
-    let mut _was_interrupted = false;
-
-    // Only prepare audio (fade out and mute) if it's not already prepared
-    if !ctx.audio_already_prepared {
-        // Update lock file state to FadingOut
-        if let Ok(mut lock_info) = read_lock_file(ctx.lock_path) {
-            lock_info.state = NotificationState::FadingOut;
-            update_lock_file(ctx.lock_path, &lock_info)?;
+// Fade durations for one sound; with --adaptive-fade at most ADAPTIVE_FADE_SHARE of its length,
+// so a short blip isn't drowned in its fades. Capping never lengthens a fade. Only a WAV header
+// is read for the length: the fades are decided right before the sound plays, too late to probe
+fn sound_fades(settings: &ServerSettings, sound_path: &Path) -> (f32, f32) {
+    let (fade_out, fade_in) = (settings.fade_out, settings.fade_in);
+    if !settings.adaptive_fade {
+        return (fade_out, fade_in);
+    }
+    match wav_duration(sound_path) {
+        Some(duration) => {
+            let cap = duration.as_secs_f32() * ADAPTIVE_FADE_SHARE;
+            (fade_out.min(cap), fade_in.min(cap))
+        }
+        None => (fade_out, fade_in),
+    }
+}
+
+// Decide whether to fade/mute the active inputs and whether to change the sink volume
+fn ducking_modes(guard: &AudioStateGuard, settings: &ServerSettings) -> (bool, bool) {
+    // Without ducking nothing is muted or changed, so cleanup has nothing to restore
+    if settings.no_duck {
+        return (false, false);
+    }
+
+    let enable_fading = !guard.unmuted_inputs.is_empty() && settings.fade_in >= 0.0 && settings.fade_out >= 0.0;
+    // Control volume when it's safe:
+    // - no active inputs, or
+    // - active inputs are being faded/muted during notification playback.
+    let enable_volume_control = guard.unmuted_inputs.is_empty() || enable_fading;
+    (enable_fading, enable_volume_control)
+}
+
+// Play a single sound synchronously without the lock file, queue or control socket
+fn play_simple(sound: &QueuedSound, settings: &ServerSettings, running: &Arc<AtomicBool>, lock_path: &Path) -> Result<()> {
+    let options = sound.options;
+    let state = get_pulseaudio_state(&settings.duck_roles, &settings.mirror_sinks)?;
+    mark_phase("sink probe");
+    let volume = request_volume(&options, sink_volume(settings, &state.default_sink), state.current_volume);
+    let volume = jittered_volume(volume, settings.jitter_volume);
+    let volume = scheduled_volume(volume, options.urgent, &settings.schedule);
+    let mut guard = AudioStateGuard::new(state);
+    guard.ramp = settings.ramp.clone();
+    guard.mute_mic = settings.mute_mic;
+    guard.fade_sink = settings.fade_sink;
+    guard.preserve_user_changes = settings.preserve_user_changes;
+    guard.verify_restore = settings.verify_restore;
+    guard.snapshot_dir = settings.snapshot_dir.clone();
+    let (enable_fading, enable_volume_control) = ducking_modes(&guard, settings);
+    let (fade_out, fade_in) = sound_fades(settings, &sound.path);
+
+    // A silent request only flashes: the audio is left alone
+    if !options.silent {
+        guard.prepare_for_notification(fade_out, enable_fading, enable_volume_control, volume, running)?;
+        mark_phase("fade-out");
+    }
+
+    let pre_hook = settings.pre_hook.as_deref().and_then(|command| run_hook("pre-play", command, sound));
+    let flash_thread = if options.flash { start_flash(running) } else { None };
+    if running.load(Ordering::SeqCst) && !options.silent {
+        let mirror_sinks: Vec<String> = guard.mirror_sinks.iter().map(|(sink, _)| sink.clone()).collect();
+        if let Some(preroll) = sound.alias.as_ref().and_then(|alias| settings.prerolls.get(alias)) {
+            let played = spawn_sound(settings, preroll, guard.sample_rate, lock_path, &mirror_sinks).and_then(|players| players.wait());
+            mark_phase("preroll");
+            if let Err(e) = played {
+                eprintln!("Warning: Failed to play preroll {}: {:#}", preroll.display(), e);
+            }
+        }
+        let played = spawn_sound(settings, &sound.path, guard.sample_rate, lock_path, &mirror_sinks).and_then(|players| {
+            mark_phase("player start");
+            players.wait()
+        });
+        mark_phase("playback");
+        if let Err(e) = played {
+            eprintln!("Error: Failed to play notification sound {}: {:#}", sound.path.display(), e);
+        }
+    }
+    if let Some(flash_thread) = flash_thread {
+        let _ = flash_thread.join();
+    }
+
+    if !options.silent {
+        guard.restore_after_notification(fade_in, enable_fading, running, &|| false)?;
+        mark_phase("fade-in");
+    }
+    let post_hook = settings.post_hook.as_deref().and_then(|command| run_hook("post-play", command, sound));
+
+    let result = guard.cleanup();
+    let _ = std::fs::remove_file(pitched_sound_path(lock_path));
+
+    // Nothing stops the hooks once we exit, so wait for them (at most HOOK_TIMEOUT)
+    for hook in [pre_hook, post_hook].into_iter().flatten() {
+        let _ = hook.join();
+    }
+    result
+}
+
+// --duck-while: duck the background as for a notification, run the command in place of the
+// player, then restore. The audio comes back however the command ends; returns its exit code.
+// The caller holds the lock file, where the ducked audio is recorded like a server's.
+fn duck_while(command: &[String], settings: &ServerSettings, running: &Arc<AtomicBool>, lock_path: &PathBuf) -> Result<i32> {
+    let (program, args) = command.split_first().context("--duck-while needs a command after --")?;
+
+    let state = get_pulseaudio_state(&settings.duck_roles, &MirrorSinks::None)?;
+    mark_phase("sink probe");
+    let mut guard = AudioStateGuard::new(state);
+    guard.lock_path = Some(lock_path.clone());
+    guard.ramp = settings.ramp.clone();
+    guard.mute_mic = settings.mute_mic;
+    guard.preserve_user_changes = settings.preserve_user_changes;
+    guard.verify_restore = settings.verify_restore;
+    guard.snapshot_dir = settings.snapshot_dir.clone();
+    // Only the background is ducked: the command plays at the sink's own volume
+    let (enable_fading, _) = ducking_modes(&guard, settings);
+    let volume = guard.current_volume;
+    set_lock_state(lock_path, NotificationState::FadingOut)?;
+    guard.prepare_for_notification(settings.fade_out, enable_fading, false, volume, running)?;
+    mark_phase("fade-out");
+
+    set_lock_state(lock_path, NotificationState::Playing)?;
+    let status = run_ducked_command(program, args).context(format!("Failed to run {}", program));
+    mark_phase("command");
+
+    // A failed restore is reported, but the exit status stays the command's
+    let _ = set_lock_state(lock_path, NotificationState::FadingIn);
+    if let Err(e) = guard.restore_after_notification(settings.fade_in, enable_fading, running, &|| false) {
+        eprintln!("Warning: Failed to restore audio: {:#}", e);
+    }
+    mark_phase("fade-in");
+    guard.cleanup_or_warn();
+    print_timings();
+
+    let status = status?;
+    Ok(status.code().unwrap_or_else(|| 128 + status.signal().unwrap_or(0)))
+}
+
+// Run the --duck-while command on our terminal and wait for it
+fn run_ducked_command(program: &str, args: &[String]) -> std::io::Result<ExitStatus> {
+    let mut command = Command::new(program);
+    command.args(args);
+    // A faked command is recorded, and `true` stands in for it
+    #[cfg(test)]
+    if let Some(hook) = *COMMAND_HOOK.lock().unwrap() {
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        hook(program, &args).map_err(|e| std::io::Error::other(e.to_string()))?;
+        command = Command::new("true");
+    }
+    command.status()
+}
+
+// Play the sound as loud as a notification would be, but through the player's own stream volume
+// so the sink and the other streams are left alone
+fn play_preview(sound_path: &Path, options: &RequestOptions, settings: &ServerSettings) -> Result<()> {
+    let state = get_pulseaudio_state(&settings.duck_roles, &settings.mirror_sinks)?;
+    let volume = request_volume(options, sink_volume(settings, &state.default_sink), state.current_volume);
+    let volume = scheduled_volume(volume, options.urgent, &settings.schedule);
+    if settings.player_cmd.is_some() {
+        eprintln!("Warning: --preview can't set the stream volume of a player command, ignoring it");
+    }
+
+    let stream_volume = preview_stream_volume(volume, state.current_volume);
+    println!(
+        "Previewing at {}% (stream volume {} of {} at the current sink volume of {}%)",
+        volume_arg(volume).trim_end_matches('%'),
+        stream_volume,
+        PA_VOLUME_NORM,
+        volume_arg(state.current_volume).trim_end_matches('%'),
+    );
+
+    let sound_path = playable_sound(sound_path, settings, state.sample_rate);
+    let options = PlayerOptions {
+        properties: &settings.stream_properties,
+        volume: Some(stream_volume),
+        ..PlayerOptions::default()
+    };
+    spawn_player(None, &sound_path.to_string_lossy(), false, &options).and_then(wait_player)
+}
+
+// paplay stream volume that, at the current sink volume, sounds like the sink set to `volume`.
+// PulseAudio multiplies stream and sink volumes on the same cubic scale, so a plain ratio works.
+fn preview_stream_volume(volume: f32, sink_volume: f32) -> u32 {
+    let ratio = if sink_volume > 0.0 { volume / sink_volume } else { volume / 100.0 };
+    (ratio * PA_VOLUME_NORM as f32).round() as u32
+}
+
+// Play one file through the players and watch the queue while it plays: a request that
+// outranks the playing sound sets should_interrupt and stops it
+fn play_monitored(ctx: &NotificationContext, sound_path: &Path, should_interrupt: &Arc<AtomicBool>) -> Result<()> {
+    let mirror_sinks: Vec<String> = ctx.guard.mirror_sinks.iter().map(|(sink, _)| sink.clone()).collect();
+    match spawn_sound(ctx.settings, sound_path, ctx.guard.sample_rate, ctx.lock_path, &mirror_sinks) {
+        Ok(players) => {
+            mark_phase("player start");
+            let player_pids = players.pids();
+            let should_interrupt_clone = should_interrupt.clone();
+
+            // Thread to check if a new notification arrived while playing
+            let notification_queue_clone = ctx.notification_queue.clone();
+            let running_clone = ctx.running.clone();
+            let play_running = Arc::new(AtomicBool::new(true));
+            let play_running_clone = play_running.clone();
+            let check_interval = ctx.settings.poll_interval;
+            let max_duration = ctx.settings.max_duration;
+            let sound_path = sound_path.to_path_buf();
+            let control_clone = Arc::clone(ctx.control);
+            let playing_options = ctx.sound.options;
+            let queue_policy = ctx.settings.queue_policy;
+            let no_interrupt_current = ctx.settings.no_interrupt_current;
+            // Nothing in the guard changes while the sound plays
+            let mut dump = ctx.guard.state_dump(ctx.audio_already_prepared);
+            let (lock_path, detached) = (ctx.lock_path.clone(), ctx.settings.detached);
+
+            let monitor_thread = thread::spawn(move || {
+                let start_time = Instant::now();
+                // Probing a compressed file may run ffprobe, so it happens here rather
+                // than before the player starts. A preroll leaves the main sound's length alone.
+                let duration = sound_duration(&sound_path);
+                let mut playing = control_clone.playing.lock().unwrap();
+                if let Some(playing) = playing.as_mut().filter(|playing| playing.sound.path == sound_path) {
+                    playing.duration = duration;
+                }
+                drop(playing);
+                let max_duration = max_duration
+                    .or(duration)
+                    .unwrap_or(Duration::from_secs_f32(DEFAULT_MAX_DURATION));
+
+                while play_running_clone.load(Ordering::SeqCst) {
+                    if DUMP_REQUESTED.swap(false, Ordering::SeqCst) {
+                        write_state_dump(&mut dump, &control_clone, &lock_path, detached);
+                    }
+
+                    // On shutdown the sound is stopped, unless it may finish (--no-interrupt-current)
+                    // and only one interrupt signal has arrived; new requests no longer interrupt it
+                    if !running_clone.load(Ordering::SeqCst) {
+                        if !no_interrupt_current || SHUTDOWN_SIGNALS.load(Ordering::SeqCst) > 1 {
+                            kill_players(&player_pids);
+                            break;
+                        }
+                        thread::sleep(check_interval);
+                        continue;
+                    }
+
+                    // A flush-now request stops the current sound but is not an interruption:
+                    // the queue is empty, so the normal fade-in follows
+                    if control_clone.skip_current.load(Ordering::SeqCst) {
+                        kill_players(&player_pids);
+                        break;
+                    }
+
+                    // If a queued request outranks what we're currently playing
+                    // (only within the first max_duration of playback)
+                    if start_time.elapsed() <= max_duration
+                        && notification_queue_clone
+                            .lock()
+                            .unwrap()
+                            .iter()
+                            .any(|queued| !queued.is_expired() && playing_options.interrupted_by(&queued.options, queue_policy))
+                    {
+                        // Signal to interrupt current playback
+                        should_interrupt_clone.store(true, Ordering::SeqCst);
+                        kill_players(&player_pids);
+                        break;
+                    }
+
+                    thread::sleep(check_interval);
+                }
+            });
+
+            let result = players.wait();
+            mark_phase("playback");
+            play_running.store(false, Ordering::SeqCst);
+            // Wait for the monitor thread to finish
+            let _ = monitor_thread.join();
+            result
+        }
+        Err(e) => Err(e),
+    }
+}
+
+// Refactored play_notification function
+fn play_notification(ctx: &mut NotificationContext) -> Result<(bool, bool)> {
+    // Track whether playback was interrupted
+    let mut _was_interrupted = false;
+    // Looked up after the sink refresh, so a sound follows a switch to another output
+    let default_volume = sink_volume(ctx.settings, &ctx.guard.default_sink);
+    let volume = request_volume(&ctx.sound.options, default_volume, ctx.guard.current_volume);
+    let volume = jittered_volume(volume, ctx.settings.jitter_volume);
+    let volume = scheduled_volume(volume, ctx.sound.options.urgent, &ctx.settings.schedule);
+
+    // A directory plays one of its files at random; the pick is remembered for --no-repeat
+    if ctx.sound.path.is_dir() {
+        let lock_info = read_lock_file(ctx.lock_path).ok();
+        let previous = lock_info
+            .as_ref()
+            .filter(|_| ctx.sound.options.no_repeat)
+            .and_then(|info| info.last_pick.as_deref())
+            .map(Path::new);
+        match pick_sound_from_dir(&ctx.sound.path, previous) {
+            Ok(path) => {
+                if let Some(mut lock_info) = lock_info {
+                    lock_info.last_pick = Some(path.to_string_lossy().to_string());
+                    update_lock_file(ctx.lock_path, &lock_info)?;
+                }
+                ctx.sound.path = path;
+            }
+            Err(e) => {
+                let error = format!("{:#}", e);
+                eprintln!("Error: Failed to pick a notification sound: {}", error);
+                ctx.control.events.emit(&Event::Failed {
+                    sound: ctx.sound.path.to_string_lossy().to_string(),
+                    error,
+                });
+                return Ok((false, false));
+            }
+        }
+    }
+
+    let (fade_out, fade_in) = sound_fades(ctx.settings, &ctx.sound.path);
+    // A silent request (a flash) has nothing to duck for; one arriving while still
+    // ducked for the previous sound keeps the audio as it is
+    let leave_audio = ctx.sound.options.silent && !ctx.audio_already_prepared;
+
+    // A sink suspended on idle takes a while to resume; waking it now overlaps
+    // that with the fade-out instead of delaying the start of the sound
+    if ctx.settings.warmup && !ctx.audio_already_prepared && !leave_audio {
+        for (sink, _) in ctx.guard.notification_sinks() {
+            if let Err(e) = pactl!("suspend-sink", &sink, "0") {
+                eprintln!("Warning: Failed to wake sink {}: {}", sink, e);
+            }
+        }
+        mark_phase("warmup");
+    }
+
+    // Only prepare audio (fade out and mute) if it's not already prepared
+    if leave_audio {
+        // Nothing to prepare
+    } else if !ctx.audio_already_prepared {
+        // Update lock file state to FadingOut
+        if let Ok(mut lock_info) = read_lock_file(ctx.lock_path) {
+            lock_info.state = NotificationState::FadingOut;
+            update_lock_file(ctx.lock_path, &lock_info)?;
+        }
+        if ctx.enable_fading && fade_out > 0.0 && ctx.guard.volume_settable {
+            ctx.control.events.emit(&Event::FadeOut {
+                from: ctx.guard.step_volume(ctx.guard.fade_state),
+                to: 0.0,
+                ms: (fade_out * ctx.guard.fade_state as f32 / FADE_STEPS as f32 * 1000.0) as u64,
+            });
         }
 
         // Prepare audio for notification
         ctx.guard.prepare_for_notification(
-            ctx.fade_out,
+            fade_out,
             ctx.enable_fading,
             ctx.enable_volume_control,
-            ctx.volume,
+            volume,
             ctx.running,
         )?;
+        mark_phase("fade-out");
 
         // Check if we should continue (user might have interrupted)
         if !ctx.running.load(Ordering::SeqCst) {
             return Ok((false, false));
         }
+    } else if ctx.enable_volume_control && ctx.guard.volume_settable {
+        // Still ducked from the previous sound, which may have played at another volume
+        ctx.guard.set_volume(volume)?;
     }
 
     // Update lock file state to Playing
@@ -632,63 +3956,105 @@ fn play_notification(ctx: &mut NotificationContext) -> Result<(bool, bool)> {
         lock_info.state = NotificationState::Playing;
         update_lock_file(ctx.lock_path, &lock_info)?;
     }
+    ctx.control.events.emit(&Event::Playing {
+        sound: ctx.sound.path.to_string_lossy().to_string(),
+        alias: ctx.sound.alias.clone(),
+    });
+
+    // Hooks run after the fade-out, right before the player starts, and once the sound
+    // is over: after the fade-in, or as soon as the next sound interrupts it
+    if let Some(command) = &ctx.settings.pre_hook {
+        run_hook("pre-play", command, &ctx.sound);
+    }
+    let sound = ctx.sound.clone();
+    *ctx.control.playing.lock().unwrap() = Some(PlayingSound {
+        sound: sound.clone(),
+        started: Instant::now(),
+        duration: wav_duration(&sound.path),
+    });
+    let _post_play_hook = PostPlayHook {
+        command: ctx.settings.post_hook.as_deref(),
+        sound: &sound,
+    };
 
     // Play the notification sound
-    let sound_path_str = ctx.sound_path.to_string_lossy().to_string();
+    let sound_path_str = ctx.sound.path.to_string_lossy().to_string();
     let should_interrupt = Arc::new(AtomicBool::new(false));
-    let should_interrupt_clone = should_interrupt.clone();
-
-    // Thread to check if a new notification arrived while playing
-    let notification_queue_clone = ctx.notification_queue.clone();
-    let running_clone = ctx.running.clone();
-    let sound_path_str_clone = sound_path_str.clone();
-    let play_running = Arc::new(AtomicBool::new(true));
-    let play_running_clone = play_running.clone();
-
-    let monitor_thread = thread::spawn(move || {
-        let check_interval = Duration::from_millis(50);
-        let start_time = std::time::Instant::now();
-
-        while running_clone.load(Ordering::SeqCst) && play_running_clone.load(Ordering::SeqCst) {
-            // If queue has new items (beyond what we're currently playing)
-            if notification_queue_clone.lock().unwrap().len() > 0 {
-                // Signal to interrupt current playback
-                should_interrupt_clone.store(true, Ordering::SeqCst);
-
-                // Try to kill paplay
-                let _ = run_command(
-                    "pkill",
-                    &["-f", &format!("paplay.*{}", sound_path_str_clone)],
-                );
-                break;
-            }
-
-            thread::sleep(check_interval);
 
-            // Safety timeout (10 seconds) to avoid hanging if something goes wrong
-            if start_time.elapsed() > Duration::from_secs(10) {
-                break;
+    // Play the sound in the main thread (we'll interrupt if needed),
+    // unless a flush-now arrived while we were still fading out
+    let flash_thread = if ctx.sound.options.flash { start_flash(ctx.running) } else { None };
+    let play_result = if ctx.control.skip_current.load(Ordering::SeqCst) || ctx.sound.options.silent {
+        Ok(())
+    } else {
+        // An attention tick configured for the alias plays to completion first; when it is
+        // interrupted or skipped, so is the main sound
+        let preroll = ctx.sound.alias.as_ref().and_then(|alias| ctx.settings.prerolls.get(alias));
+        if let Some(preroll) = preroll {
+            if let Err(e) = play_monitored(ctx, preroll, &should_interrupt) {
+                if !should_interrupt.load(Ordering::SeqCst)
+                    && !ctx.control.skip_current.load(Ordering::SeqCst)
+                    && ctx.running.load(Ordering::SeqCst)
+                {
+                    eprintln!("Warning: Failed to play preroll {}: {:#}", preroll.display(), e);
+                }
+            }
+            mark_phase("preroll");
+            if let Some(playing) = ctx.control.playing.lock().unwrap().as_mut() {
+                playing.started = Instant::now();
             }
         }
-    });
+        if should_interrupt.load(Ordering::SeqCst)
+            || ctx.control.skip_current.load(Ordering::SeqCst)
+            || !ctx.running.load(Ordering::SeqCst)
+        {
+            Ok(())
+        } else {
+            play_monitored(ctx, &ctx.sound.path, &should_interrupt)
+        }
+    };
+    if let Some(flash_thread) = flash_thread {
+        let _ = flash_thread.join();
+    }
+    ctx.control.playing.lock().unwrap().take();
+    let skipped = ctx.control.skip_current.swap(false, Ordering::SeqCst);
 
-    // Play the sound in the main thread (we'll interrupt if needed)
-    let play_result = run_command("paplay", &[&sound_path_str]);
-    play_running.store(false, Ordering::SeqCst);
-    // Wait for the monitor thread to finish
-    let _ = monitor_thread.join();
-    
-    // Check if paplay failed
+    // Check if the player failed (a skipped or interrupted sound is expected to be killed).
+    // A failed sound still goes through the normal restore below.
     if let Err(e) = play_result {
-        eprintln!("Warning: Failed to play notification sound: {}", e);
-        eprintln!("Sound path: {}", sound_path_str);
+        if !skipped && !should_interrupt.load(Ordering::SeqCst) && ctx.running.load(Ordering::SeqCst) {
+            let error = format!("{:#}", e);
+            eprintln!("Error: Failed to play notification sound {}: {}", sound_path_str, error);
+            ctx.control.events.emit(&Event::Failed {
+                sound: sound_path_str.clone(),
+                error,
+            });
+            // Repeating a sound that can't be played would only fail again
+            if ctx.control.attention.lock().unwrap().take_if(|alert| alert.id == ctx.sound.id).is_some() {
+                eprintln!("Warning: Stopped repeating {} until acknowledged", sound_path_str);
+            }
+        }
     }
 
     // Check if we were interrupted or have a new notification waiting
-    if should_interrupt.load(Ordering::SeqCst) || !ctx.notification_queue.lock().unwrap().is_empty() {
+    if should_interrupt.load(Ordering::SeqCst) || has_pending(&ctx.notification_queue.lock().unwrap()) {
         _was_interrupted = true;
-        // Keep fade_state as is - we're already faded out
-        // Skip fade-in if interrupted or new notification waiting
+        ctx.control.events.emit(&Event::Interrupted);
+        // By default keep fade_state as is - we're already faded out - and skip the fade-in,
+        // so the next sound starts at once. With --fade-on-interrupt the background comes back
+        // for a moment and the next sound fades it out again from the level it reached
+        if ctx.settings.fade_on_interrupt
+            && !leave_audio
+            && ctx.enable_fading
+            && fade_in > 0.0
+            && ctx.guard.volume_settable
+            && ctx.running.load(Ordering::SeqCst)
+        {
+            let deadline = Instant::now() + INTERRUPT_FADE;
+            ctx.guard.restore_after_notification(fade_in, ctx.enable_fading, ctx.running, &|| {
+                Instant::now() >= deadline
+            })?;
+        }
         return Ok((false, true));
     }
 
@@ -697,18 +4063,51 @@ fn play_notification(ctx: &mut NotificationContext) -> Result<(bool, bool)> {
         return Ok((false, false));
     }
 
+    // While an alert waits for --ack the audio stays ducked for its next repeat
+    if ctx.control.attention.lock().unwrap().is_some() {
+        return Ok((false, true));
+    }
+
+    // --no-restore: stay ducked so the script's next sound starts at once
+    if ctx.sound.options.no_restore {
+        *ctx.control.hold_until.lock().unwrap() = Some(Instant::now() + NO_RESTORE_TIMEOUT);
+        return Ok((false, true));
+    }
+
+    // Never ducked, so there is nothing to restore
+    if leave_audio {
+        if let Ok(mut lock_info) = read_lock_file(ctx.lock_path) {
+            lock_info.state = NotificationState::Idle;
+            update_lock_file(ctx.lock_path, &lock_info)?;
+        }
+        ctx.control.events.emit(&Event::Idle);
+        return Ok((true, false));
+    }
+
     // Update lock file state to FadingIn
     if let Ok(mut lock_info) = read_lock_file(ctx.lock_path) {
         lock_info.state = NotificationState::FadingIn;
         update_lock_file(ctx.lock_path, &lock_info)?;
     }
+    if ctx.enable_fading && fade_in > 0.0 && ctx.guard.volume_settable {
+        ctx.control.events.emit(&Event::FadeIn {
+            from: ctx.guard.step_volume(ctx.guard.fade_state),
+            to: ctx.guard.current_volume,
+            ms: (fade_in * (FADE_STEPS - ctx.guard.fade_state) as f32 / FADE_STEPS as f32 * 1000.0) as u64,
+        });
+    }
 
     // Restore audio state after notification
-    ctx.guard.restore_after_notification(ctx.fade_in, ctx.enable_fading, ctx.running)?;
+    let queue = ctx.notification_queue;
+    ctx.guard.restore_after_notification(fade_in, ctx.enable_fading, ctx.running, &|| {
+        has_pending(&queue.lock().unwrap())
+    })?;
+    mark_phase("fade-in");
 
     // Check again after fade-in if we were interrupted
-    if !ctx.notification_queue.lock().unwrap().is_empty() {
+    if has_pending(&ctx.notification_queue.lock().unwrap()) {
         _was_interrupted = true;
+        ctx.control.events.emit(&Event::Interrupted);
         return Ok((false, true));
     }
 
@@ -717,212 +4116,2729 @@ fn play_notification(ctx: &mut NotificationContext) -> Result<(bool, bool)> {
         lock_info.state = NotificationState::Idle;
         update_lock_file(ctx.lock_path, &lock_info)?;
     }
+    ctx.control.events.emit(&Event::Idle);
 
     // Return completion status: (completed successfully, was interrupted)
     Ok((true, false))
 }
 
-fn load_config(config_path: &Option<PathBuf>) -> Result<Config> {
-    // If config path is provided, use it
-    if let Some(path) = config_path {
-        if path.exists() {
-            let file = std::fs::File::open(path).context("Failed to open config file")?;
-            return serde_yaml::from_reader(file).context("Failed to parse config file");
-        }
-    }
-
-    // Check default locations
-    let possible_paths = vec![
-        PathBuf::from("./vh-notification-sound.yml"),
-        dirs::config_dir()
-            .map(|p| p.join("vh-notification-sound.yml"))
-            .unwrap_or_default(),
-        dirs::home_dir()
-            .map(|p| p.join(".vh-notification-sound.yml"))
-            .unwrap_or_default(),
-    ];
+fn start_control_socket(
+    socket_path: &Path,
+    poll_interval: Duration,
+    running: Arc<AtomicBool>,
+    control: Arc<ControlState>,
+) -> Result<()> {
+    // A socket file left behind by a dead server would make bind fail
+    let _ = std::fs::remove_file(socket_path);
+    let listener = UnixListener::bind(socket_path).context(runtime_path_error("bind control socket", socket_path))?;
+    listener
+        .set_nonblocking(true)
+        .context(format!("Failed to configure control socket {}", socket_path.display()))?;
 
-    for path in possible_paths {
-        if path.exists() {
-            let file = std::fs::File::open(&path).context("Failed to open config file")?;
-            return serde_yaml::from_reader(file).context("Failed to parse config file");
+    thread::spawn(move || {
+        while running.load(Ordering::SeqCst) {
+            match listener.accept() {
+                Ok((stream, _)) => {
+                    // A client that connects but never writes only holds up its own thread
+                    let control = control.clone();
+                    thread::spawn(move || {
+                        if let Err(e) = handle_control_connection(stream, &control) {
+                            eprintln!("Warning: Invalid control request: {}", e);
+                        }
+                    });
+                }
+                Err(_) => thread::sleep(poll_interval),
+            }
         }
-    }
+    });
 
-    // Return default config if no config file found
-    Ok(Config::default())
+    Ok(())
 }
 
-fn resolve_sound_path(sound: &str, config: &Config) -> Result<PathBuf> {
-    // Check if the sound is an alias in the config
-    if let Some(path) = config.sounds.get(sound) {
-        return expand_tilde(path);
-    }
+fn start_remote_listener(
+    addr: SocketAddr,
+    poll_interval: Duration,
+    running: Arc<AtomicBool>,
+    remote: Arc<RemoteListener>,
+) -> Result<()> {
+    let listener = TcpListener::bind(addr).context(format!("Failed to listen on {}", addr))?;
+    listener.set_nonblocking(true)?;
+
+    thread::spawn(move || {
+        while running.load(Ordering::SeqCst) {
+            match listener.accept() {
+                Ok((stream, peer)) => {
+                    // A client that connects but never writes only holds up its own thread
+                    let remote = remote.clone();
+                    thread::spawn(move || {
+                        if let Err(e) = handle_remote_connection(stream, peer, &remote) {
+                            eprintln!("Warning: Invalid remote request from {}: {}", peer, e);
+                        }
+                    });
+                }
+                Err(_) => thread::sleep(poll_interval),
+            }
+        }
+    });
 
-    // Otherwise, treat it as a direct path
-    expand_tilde(sound)
+    Ok(())
 }
 
-fn expand_tilde(path: &str) -> Result<PathBuf> {
-    if path.starts_with("~/") || path == "~" {
-        let home_dir = dirs::home_dir().context("Could not determine home directory")?;
-        if path == "~" {
-            Ok(home_dir)
-        } else {
-            Ok(home_dir.join(&path[2..]))
+fn handle_remote_connection(mut stream: TcpStream, peer: SocketAddr, remote: &RemoteListener) -> Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    stream.set_write_timeout(Some(Duration::from_secs(5)))?;
+
+    let request: RemoteRequest = match decode_message(&read_frame(&mut stream)?, "remote client") {
+        Ok(request) => request,
+        Err(e) => {
+            let response = ControlResponse {
+                ok: false,
+                message: format!("{:#}", e),
+                queue: None,
+            };
+            return write_frame(&mut stream, &encode_message(&response)?);
+        }
+    };
+    let settings = remote.settings.lock().unwrap().clone();
+    let response = if !token_matches(&settings.token, &request.token) {
+        ControlResponse {
+            ok: false,
+            message: "Invalid token.".to_string(),
+            queue: None,
         }
     } else {
-        Ok(PathBuf::from(path))
-    }
-}
+        match queue_remote_request(&request, &settings, &remote.control) {
+            Ok((path, queued)) => {
+                if remote.foreground {
+                    println!("request: {} from {}", path.display(), peer);
+                }
+                let message = match queued {
+                    Enqueued::Queued => format!("Queued {} on the remote server.", path.display()),
+                    Enqueued::Duplicate => format!("Dropped {}, its --once key was already played.", path.display()),
+                    Enqueued::Overflow => format!("Dropped {}, the remote server's queue is full.", path.display()),
+                };
+                ControlResponse { ok: true, message, queue: None }
+            }
+            Err(e) => ControlResponse {
+                ok: false,
+                message: format!("{:#}", e),
+                queue: None,
+            },
+        }
+    };
 
-fn run_command(cmd: &str, args: &[&str]) -> Result<String> {
-    let output = Command::new(cmd)
-        .args(args)
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .output()
-        .context(format!("Failed to execute command: {} {:?}", cmd, args))?;
+    write_frame(&mut stream, &encode_message(&response)?)
+}
 
-    if output.status.success() {
-        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
-    } else {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        anyhow::bail!("Command failed: {} {:?}\nError: {}", cmd, args, stderr)
+// Resolve the requested sound against the server's own config and queue it, telling
+// whether it was dropped (for its --once key or a full queue). Remote clients may only name
+// configured aliases and categories or built-in tones, never files on this machine.
+fn queue_remote_request(request: &RemoteRequest, settings: &RemoteSettings, control: &ControlState) -> Result<(PathBuf, Enqueued)> {
+    request.options.validate()?;
+    let spec = parse_sound_spec(&request.sound)?;
+    let config = &settings.config;
+    let port_override = active_port_override(config);
+    let alias = match config.categories.get(&spec.sound) {
+        Some(alias) => alias.as_str(),
+        None => spec.sound.as_str(),
+    };
+    let configured = config.sounds.contains_key(alias)
+        || port_override.is_some_and(|o| o.sounds.contains_key(alias))
+        || alias.starts_with(BUILTIN_PREFIX);
+    if !configured {
+        anyhow::bail!("Unknown sound '{}': remote requests may only name configured aliases, categories or builtin: tones", spec.sound);
+    }
+    let sound = scheduled_sound(alias, &config.schedule);
+    let path = resolve_sound_path(sound, config, port_override)?;
+    check_sound_dir(&path)?;
+
+    let mut options = request.options;
+    options.volume = options
+        .volume
+        .or(spec.volume.map(|volume| volume.clamp(0.0, 100.0)))
+        .or(options.urgent.then(|| (settings.volume + URGENT_VOLUME_BOOST).min(100.0)));
+
+    // Queued now it would go away with the server unplayed; refused, the client can send it again
+    if control.exiting.load(Ordering::SeqCst) {
+        anyhow::bail!("Notification server is exiting.");
     }
+    let sound = QueuedSound::new(path.clone(), Some(spec.sound), options).with_once(request.once.clone());
+    let queued = enqueue(control, sound);
+    Ok((path, queued))
 }
 
-fn get_pulseaudio_state() -> Result<PulseAudioState> {
-    // Get default sink
-    let default_sink = pactl!("info")?
-        .lines()
-        .find(|line| line.contains("Default Sink"))
-        .map(|line| line.split(": ").nth(1).unwrap_or("").trim().to_string())
-        .context("Failed to get default sink")?;
-
-    // Get current volume
-    let volume_output = pactl!("list", "sinks")?;
-    let current_volume_str = volume_output
-        .lines()
-        .skip_while(|line| !line.contains(&format!("Name: {}", default_sink)))
-        .take(15)
-        .find(|line| line.contains("Volume: front-left"))
-        .and_then(|line| line.split_whitespace().nth(4))
-        .and_then(|vol| vol.trim_end_matches('%').parse::<u8>().ok())
-        .context("Failed to get current volume")?;
+fn send_remote_request(addr: &str, request: &RemoteRequest) -> Result<ControlResponse> {
+    let mut stream = TcpStream::connect(addr).context(format!("Failed to connect to {}", addr))?;
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    write_frame(&mut stream, &encode_message(request)?)?;
+    decode_message(&read_frame(&mut stream)?, "remote server")
+}
 
-    // Get unmuted sink inputs
-    let sink_inputs_output = pactl!("list", "short", "sink-inputs")?;
-    let sink_input_ids: Vec<String> = sink_inputs_output
-        .lines()
-        .filter(|line| !line.is_empty())
-        .map(|line| line.split_whitespace().next().unwrap_or("").to_string())
-        .collect();
+// Remote requests and replies are JSON payloads prefixed with their length as a big-endian u32
+fn read_frame(stream: &mut impl Read) -> Result<Vec<u8>> {
+    let mut len = [0u8; 4];
+    stream.read_exact(&mut len)?;
+    let len = u32::from_be_bytes(len) as usize;
+    if len > MAX_FRAME_SIZE {
+        anyhow::bail!("Frame of {} bytes exceeds the {} byte limit", len, MAX_FRAME_SIZE);
+    }
+    let mut payload = vec![0u8; len];
+    stream.read_exact(&mut payload)?;
+    Ok(payload)
+}
 
-    let sink_inputs_details = pactl!("list", "sink-inputs")?;
-    let mut unmuted_inputs = Vec::new();
+fn write_frame(stream: &mut impl Write, payload: &[u8]) -> Result<()> {
+    stream.write_all(&(payload.len() as u32).to_be_bytes())?;
+    stream.write_all(payload)?;
+    Ok(())
+}
 
-    for id in sink_input_ids {
-        if !id.is_empty() {
-            let is_muted = sink_inputs_details
-                .lines()
-                .skip_while(|line| !line.contains(&format!("Sink Input #{}", id)))
-                .take(15)
-                .find(|line| line.contains("Mute:"))
-                .map(|line| line.contains("yes"))
-                .unwrap_or(true);
+// Compare every byte so the response time doesn't reveal how much of the token matched
+fn token_matches(expected: &str, given: &str) -> bool {
+    expected.len() == given.len() && expected.bytes().zip(given.bytes()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
 
-            if !is_muted {
-                unmuted_inputs.push(id);
-            }
+// A --stream-property KEY=VALUE
+fn parse_stream_property(value: &str) -> Result<(String, String), String> {
+    match value.split_once('=') {
+        Some((key, property)) if !key.trim().is_empty() => {
+            let key = key.trim();
+            check_stream_property(key, property).map_err(|e| format!("invalid stream property '{}': {}", value, e))?;
+            Ok((key.to_string(), property.to_string()))
         }
+        _ => Err(format!("invalid stream property '{}' (expected KEY=VALUE, e.g. media.role=event)", value)),
     }
-
-    Ok(PulseAudioState {
-        default_sink,
-        current_volume: current_volume_str,
-        unmuted_inputs,
-    })
 }
 
-fn update_lock_file(lock_path: &PathBuf, lock_info: &LockInfo) -> Result<()> {
-    let file = OpenOptions::new()
-        .write(true)
-        .create(true)
-        .truncate(true)
-        .open(lock_path)?;
-
-    serde_json::to_writer(&file, &lock_info)?;
-
+// PULSE_PROP quotes each value in double quotes with no way to escape them, and splits at
+// whitespace outside of them
+fn check_stream_property(key: &str, value: &str) -> Result<(), String> {
+    if key.chars().any(|c| c.is_whitespace() || c == '"' || c == '\\') {
+        return Err("the key may not contain whitespace, quotes or backslashes".to_string());
+    }
+    if value.contains(['"', '\\']) {
+        return Err("the value may not contain quotes or backslashes".to_string());
+    }
     Ok(())
 }
 
-fn read_lock_file(lock_path: &PathBuf) -> Result<LockInfo> {
-    let file = OpenOptions::new().read(true).open(lock_path)?;
+// Durations such as "2s", "500ms", "1m" or "1h"; a plain number is seconds
+fn parse_duration(value: &str) -> Result<Duration, String> {
+    let value = value.trim();
+    let (number, scale) = if let Some(ms) = value.strip_suffix("ms") {
+        (ms, 0.001)
+    } else if let Some(secs) = value.strip_suffix('s') {
+        (secs, 1.0)
+    } else if let Some(mins) = value.strip_suffix('m') {
+        (mins, 60.0)
+    } else if let Some(hours) = value.strip_suffix('h') {
+        (hours, 3600.0)
+    } else {
+        (value, 1.0)
+    };
+    number
+        .trim()
+        .parse::<f32>()
+        .ok()
+        .filter(|n| n.is_finite() && *n >= 0.0)
+        .and_then(|n| Duration::try_from_secs_f32(n * scale).ok())
+        .ok_or_else(|| format!("invalid duration '{}' (expected e.g. 2s, 500ms, 1m or 1h)", value))
+}
 
-    let lock_info: LockInfo = serde_json::from_reader(file)?;
+// An --every interval; at most MAX_EVERY, so the time of the next play can always be computed
+fn parse_every(value: &str) -> Result<Duration, String> {
+    let every = parse_duration(value)?;
+    if every.as_secs_f32() > MAX_EVERY {
+        return Err(format!("interval '{}' is too long (at most 720h)", value.trim()));
+    }
+    Ok(every)
+}
 
-    Ok(lock_info)
+// A bare port binds localhost only; anything else must be an explicit address
+fn parse_listen_addr(addr: &str) -> Result<SocketAddr> {
+    if let Ok(port) = addr.parse::<u16>() {
+        return Ok(SocketAddr::from(([127, 0, 0, 1], port)));
+    }
+    addr.to_socket_addrs()
+        .context(format!("Invalid listen address {}", addr))?
+        .next()
+        .context(format!("Invalid listen address {}", addr))
 }
 
-fn acquire_lock(lock_path: &PathBuf, sound_path: &str) -> Result<Option<File>> {
-    // Check if lock file exists and is valid
-    if lock_path.exists() {
-        // Try to read the lock file as JSON
-        match read_lock_file(lock_path) {
-            Ok(lock_info) => {
-                // Check if the process in the lock file is still running
-                let proc_path = PathBuf::from(format!("/proc/{}", lock_info.pid));
-                if proc_path.exists() {
-                    // The process is still running, send a new notification request
-                    let mut updated_info = lock_info;
-                    updated_info.new_request = Some(sound_path.to_string());
-                    update_lock_file(lock_path, &updated_info)?;
-                    return Ok(Some(File::open(lock_path)?));
-                } else {
-                    // Process is not running, remove stale lock
-                    std::fs::remove_file(lock_path)?;
+fn handle_control_connection(mut stream: UnixStream, control: &ControlState) -> Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(Duration::from_secs(1)))?;
+
+    let mut line = String::new();
+    BufReader::new(&stream).read_line(&mut line)?;
+    let request: ControlRequest = match decode_message(line.trim().as_bytes(), "client") {
+        Ok(request) => request,
+        Err(e) => {
+            return reply(
+                &mut stream,
+                &ControlResponse {
+                    ok: false,
+                    message: format!("{:#}", e),
+                    queue: None,
+                },
+            );
+        }
+    };
+
+    let response = match request {
+        ControlRequest::Subscribe => {
+            control.events.subscribe(stream);
+            return Ok(());
+        }
+        ControlRequest::Flush { now } => flush_queue(control, now),
+        ControlRequest::Play {
+            sound,
+            alias,
+            options,
+            pid,
+            once,
+        } => {
+            if control.events.log {
+                match pid {
+                    Some(pid) => println!("request: {} from PID {}", sound, pid),
+                    None => println!("request: {}", sound),
                 }
             }
-            Err(_) => {
-                // Lock file exists but isn't in our format, try to read it as plain text for backward compatibility
-                let mut file = File::open(lock_path)?;
-                let mut contents = String::new();
-                file.read_to_string(&mut contents)?;
-
-                // Check if the process in the lock file is still running
-                if let Ok(pid) = contents.trim().parse::<i32>() {
-                    let proc_path = PathBuf::from(format!("/proc/{}", pid));
-                    if proc_path.exists() {
-                        return Err(anyhow::anyhow!(
-                            "Another notification is currently playing (PID: {}).",
-                            pid
-                        ));
+            // The client waits for the exiting server to go away and starts a new one
+            if control.exiting.load(Ordering::SeqCst) {
+                return reply(
+                    &mut stream,
+                    &ControlResponse {
+                        ok: false,
+                        message: "Notification server is exiting.".to_string(),
+                        queue: None,
+                    },
+                );
+            }
+            if let Err(e) = options.validate() {
+                return reply(
+                    &mut stream,
+                    &ControlResponse {
+                        ok: false,
+                        message: format!("{:#}", e),
+                        queue: None,
+                    },
+                );
+            }
+            let request = QueuedSound::new(PathBuf::from(&sound), alias, options).with_once(once);
+            let message = match enqueue(control, request) {
+                Enqueued::Queued => format!("Queued {}.", sound),
+                Enqueued::Duplicate => {
+                    if control.events.log {
+                        println!("dropped: {} (already played once)", sound);
                     }
+                    format!("Dropped {}, its --once key was already played.", sound)
                 }
-
-                // If the process is not running, remove the stale lock
-                std::fs::remove_file(lock_path)?;
+                Enqueued::Overflow => {
+                    if control.events.log {
+                        println!("dropped: {} (queue full)", sound);
+                    }
+                    format!("Dropped {}, the queue is full.", sound)
+                }
+            };
+            ControlResponse { ok: true, message, queue: None }
+        }
+        ControlRequest::List => list_queue(control),
+        ControlRequest::Cancel { id } => cancel_request(control, id),
+        ControlRequest::Reload => {
+            RELOAD_REQUESTED.store(true, Ordering::SeqCst);
+            ControlResponse {
+                ok: true,
+                message: "Config reload requested.".to_string(),
+                queue: None,
+            }
+        }
+        ControlRequest::Ack => acknowledge(control),
+        ControlRequest::Restore => {
+            let mut hold_until = control.hold_until.lock().unwrap();
+            match hold_until.as_mut() {
+                Some(until) => {
+                    // The server loop fades the audio in once it sees the hold has run out
+                    *until = Instant::now();
+                    ControlResponse {
+                        ok: true,
+                        message: "Restoring audio.".to_string(),
+                        queue: None,
+                    }
+                }
+                None => ControlResponse {
+                    ok: false,
+                    message: "No audio is held ducked by --no-restore.".to_string(),
+                    queue: None,
+                },
             }
         }
-    }
-
-    // Create new lock file with initial state
-    let initial_lock_info = LockInfo {
-        pid: std::process::id(),
-        state: NotificationState::Idle,
-        new_request: None,
     };
 
-    update_lock_file(lock_path, &initial_lock_info)?;
+    reply(&mut stream, &response)
+}
 
-    // Return None to indicate we're starting a new process
-    Ok(None)
+fn reply(stream: &mut UnixStream, response: &ControlResponse) -> Result<()> {
+    stream.write_all(&encode_message(response)?)?;
+    stream.write_all(b"\n")?;
+    Ok(())
 }
 
-fn print_help_info() {
-    println!("VH Notification Sound");
-    println!("A simple application that plays notification sounds while temporarily fading out any currently playing audio.");
+fn flush_queue(control: &ControlState, now: bool) -> ControlResponse {
+    let mut flushed = {
+        let mut queue = control.notification_queue.lock().unwrap();
+        let count = queue.len();
+        forget_once_keys(control, queue.iter());
+        queue.clear();
+        save_queue(control, &queue);
+        count
+    };
+
+    // A request written to the lock file but not yet picked up is flushed too
+    if let Ok(mut lock_info) = read_lock_file(&control.lock_path) {
+        if lock_info.new_request.take().is_some() {
+            lock_info.request_pid = None;
+            lock_info.request_alias = None;
+            lock_info.request_options = RequestOptions::default();
+            lock_info.request_once = None;
+            flushed += 1;
+            let _ = update_lock_file(&control.lock_path, &lock_info);
+        }
+    }
+
+    if now {
+        control.skip_current.store(true, Ordering::SeqCst);
+    }
+
+    ControlResponse {
+        ok: true,
+        message: format!(
+            "Flushed {} queued notification(s){}.",
+            flushed,
+            if now { " and stopped the current sound" } else { "" }
+        ),
+        queue: None,
+    }
+}
+
+fn list_queue(control: &ControlState) -> ControlResponse {
+    let playing = control.playing.lock().unwrap().as_ref().map(|playing| {
+        let elapsed = playing.started.elapsed();
+        QueueEntry {
+            elapsed: Some(elapsed.as_secs_f32()),
+            remaining: playing.duration.map(|duration| duration.saturating_sub(elapsed).as_secs_f32()),
+            ..QueueEntry::new(&playing.sound)
+        }
+    });
+    let queued: Vec<QueueEntry> = control
+        .notification_queue
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|queued| !queued.is_expired())
+        .map(QueueEntry::new)
+        .collect();
+    let awaiting_ack = control.attention.lock().unwrap().as_ref().map(QueueEntry::new);
+
+    ControlResponse {
+        ok: true,
+        message: format!("{} playing, {} queued.", usize::from(playing.is_some()), queued.len()),
+        queue: Some(QueueListing { playing, queued, awaiting_ack }),
+    }
+}
+
+// Stop repeating the --until-ack sound, cutting it short if it is playing
+fn acknowledge(control: &ControlState) -> ControlResponse {
+    let alert = {
+        let queue = control.notification_queue.lock().unwrap();
+        let alert = control.attention.lock().unwrap().take();
+        save_queue(control, &queue);
+        alert
+    };
+    let Some(alert) = alert else {
+        return ControlResponse {
+            ok: false,
+            message: "No alert is waiting for acknowledgement.".to_string(),
+            queue: None,
+        };
+    };
+    if control.playing.lock().unwrap().as_ref().is_some_and(|playing| playing.sound.id == alert.id) {
+        control.skip_current.store(true, Ordering::SeqCst);
+    }
+    let sound = alert.path.to_string_lossy().to_string();
+    let message = format!("Acknowledged {}.", sound);
+    control.events.emit(&Event::Acknowledged { sound });
+    ControlResponse { ok: true, message, queue: None }
+}
+
+fn cancel_request(control: &ControlState, id: u64) -> ControlResponse {
+    let removed = {
+        let mut queue = control.notification_queue.lock().unwrap();
+        let before = queue.len();
+        forget_once_keys(control, queue.iter().filter(|queued| queued.id == id));
+        queue.retain(|queued| queued.id != id);
+        save_queue(control, &queue);
+        queue.len() < before
+    };
+    let message = if removed {
+        format!("Cancelled queued request {}.", id)
+    } else if control.playing.lock().unwrap().as_ref().is_some_and(|playing| playing.sound.id == id) {
+        control.skip_current.store(true, Ordering::SeqCst);
+        format!("Stopped request {}.", id)
+    } else {
+        return ControlResponse {
+            ok: false,
+            message: format!("No queued or playing request with id {}.", id),
+            queue: None,
+        };
+    };
+    ControlResponse { ok: true, message, queue: None }
+}
+
+// Print the reply to --list-queue
+fn print_queue(listing: &QueueListing) {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.as_secs_f64())
+        .unwrap_or_default();
+    let describe = |entry: &QueueEntry| {
+        format!(
+            "#{} {}{} [{}{}]",
+            entry.id,
+            entry.sound,
+            entry
+                .alias
+                .as_ref()
+                .filter(|alias| **alias != entry.sound)
+                .map(|alias| format!(" ({})", alias))
+                .unwrap_or_default(),
+            format!("{:?}", entry.priority).to_lowercase(),
+            if entry.urgent { ", urgent" } else { "" },
+        )
+    };
+
+    match &listing.playing {
+        Some(entry) => {
+            let mut timing = format!("{:.1}s elapsed", entry.elapsed.unwrap_or_default());
+            if let Some(remaining) = entry.remaining {
+                timing.push_str(&format!(", {:.1}s left", remaining));
+            }
+            println!("Playing: {} {}", describe(entry), timing);
+        }
+        None => println!("Playing: nothing"),
+    }
+    if let Some(entry) = &listing.awaiting_ack {
+        println!("Repeating until --ack: {}", describe(entry));
+    }
+    if listing.queued.is_empty() {
+        println!("Queue is empty.");
+        return;
+    }
+    println!("Queued:");
+    for entry in &listing.queued {
+        println!("  {} queued {:.1}s ago", describe(entry), (now - entry.queued_at).max(0.0));
+    }
+}
+
+fn send_control_request(socket_path: &Path, request: &ControlRequest) -> Result<ControlResponse> {
+    let response = exchange_control_request(socket_path, request)?;
+    if !response.ok {
+        anyhow::bail!("{}", response.message);
+    }
+    Ok(response)
+}
+
+// Send a request and return the server's reply, whether or not it accepted the request
+fn exchange_control_request(socket_path: &Path, request: &ControlRequest) -> Result<ControlResponse> {
+    let mut stream = UnixStream::connect(socket_path).context("No notification server is running")?;
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    stream.write_all(&encode_message(request)?)?;
+    stream.write_all(b"\n")?;
+
+    let mut line = String::new();
+    BufReader::new(&stream).read_line(&mut line)?;
+    decode_message(line.trim().as_bytes(), "notification server")
+}
+
+fn subscribe_events(socket_path: &Path) -> Result<()> {
+    // Servers are short-lived, so keep reconnecting until interrupted
+    let mut stdout = std::io::stdout();
+    loop {
+        let Ok(mut stream) = UnixStream::connect(socket_path) else {
+            thread::sleep(Duration::from_millis(100));
+            continue;
+        };
+        let request = encode_message(&ControlRequest::Subscribe)?;
+        if stream.write_all(&request).and_then(|_| stream.write_all(b"\n")).is_err() {
+            continue;
+        }
+
+        for line in BufReader::new(stream).lines() {
+            let Ok(line) = line else { break };
+            writeln!(stdout, "{}", line)?;
+            stdout.flush()?;
+        }
+    }
+}
+
+fn load_config(config_path: &Option<PathBuf>, cwd_config: bool) -> Result<Config> {
+    // Older versions read it unasked; say why it no longer applies
+    if !cwd_config && config_path.is_none() && Path::new(CWD_CONFIG_PATH).exists() {
+        eprintln!("Warning: Ignoring {} in the working directory; pass --cwd-config to use it", CWD_CONFIG_PATH);
+    }
+    match find_config_path(config_path, cwd_config) {
+        Some(path) => read_config_file(&path),
+        // Return default config if no config file found
+        None => Ok(Config::default()),
+    }
+}
+
+// Config in the working directory, only searched with --cwd-config: a config picked up from
+// wherever a script happens to run would be a surprise, and anyone who can write there could
+// change what (and which player command) we run
+const CWD_CONFIG_PATH: &str = "./vh-notification-sound.yml";
+
+// The config file load_config reads, if any
+fn find_config_path(config_path: &Option<PathBuf>, cwd_config: bool) -> Option<PathBuf> {
+    // If config path is provided, use it
+    if let Some(path) = config_path {
+        if path.exists() {
+            return Some(path.clone());
+        }
+    }
+
+    // Check default locations
+    let possible_paths = vec![
+        cwd_config.then(|| PathBuf::from(CWD_CONFIG_PATH)),
+        dirs::config_dir().map(|p| p.join("vh-notification-sound.yml")),
+        dirs::home_dir().map(|p| p.join(".vh-notification-sound.yml")),
+    ];
+
+    possible_paths.into_iter().flatten().find(|path| path.exists())
+}
+
+fn read_config_file(path: &Path) -> Result<Config> {
+    let file = std::fs::File::open(path).context("Failed to open config file")?;
+    serde_yaml::from_reader(file).context("Failed to parse config file")
+}
+
+fn write_config_file(path: &Path, config: &Config, header: &str) -> Result<()> {
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent)
+            .context(format!("Failed to create config directory {}", parent.display()))?;
+    }
+
+    let yaml = serde_yaml::to_string(config).context("Failed to serialize config")?;
+    std::fs::write(path, format!("{}\n{}", header, yaml))
+        .context(format!("Failed to write config file {}", path.display()))
+}
+
+// Where a new user config file is created
+fn default_config_path() -> Result<PathBuf> {
+    Ok(dirs::config_dir()
+        .context("Could not determine config directory")?
+        .join("vh-notification-sound.yml"))
+}
+
+fn save_defaults(config_path: &Option<PathBuf>, cwd_config: bool, fade_out: f32, fade_in: f32, volume: f32) -> Result<PathBuf> {
+    // An explicit --config is the target even if it does not exist yet
+    let path = match config_path.clone().or_else(|| find_config_path(&None, cwd_config)) {
+        Some(path) => path,
+        None => default_config_path()?,
+    };
+
+    // Start from what is on disk so the sounds map and other keys survive
+    let mut config = if path.exists() {
+        read_config_file(&path)?
+    } else {
+        Config {
+            poll_interval: None,
+            ..Config::default()
+        }
+    };
+    config.fade_out = Some(fade_out);
+    config.fade_in = Some(fade_in);
+    config.volume = Some(volume);
+
+    write_config_file(&path, &config, CONFIG_HEADER)?;
+    Ok(path)
+}
+
+// Example sounds offered by --config-init when present on the system
+const EXAMPLE_SOUNDS: &[(&str, &str)] = &[
+    ("default", "/usr/share/sounds/freedesktop/stereo/message.oga"),
+    ("error", "/usr/share/sounds/freedesktop/stereo/dialog-error.oga"),
+    ("warning", "/usr/share/sounds/freedesktop/stereo/dialog-warning.oga"),
+    ("complete", "/usr/share/sounds/freedesktop/stereo/complete.oga"),
+    ("bell", "/usr/share/sounds/freedesktop/stereo/bell.oga"),
+];
+
+const CONFIG_HEADER: &str = "# vh-notification-sound configuration file\n";
+
+const CONFIG_INIT_HEADER: &str = "# vh-notification-sound configuration file
+#
+# fade_out / fade_in: fade durations in seconds
+# volume:             output volume percentage for notification sounds (0-100)
+# sounds:             aliases usable as `vh-notification-sound <alias>`;
+#                     paths may start with ~/
+#
+# Other keys: poll_interval, max_duration, idle_timeout, persist_queue, max_queue, overflow,
+# overflow_sound, player_cmd, backend_player, duck_roles, stream_properties, resample,
+# verify_restore, fade_on_interrupt, adaptive_fade, enabled_if, preroll, fallback, ports,
+# sink_volumes, sinks, schedule, webhook. See the README for details.
+";
+
+fn init_config(config_path: &Option<PathBuf>, force: bool) -> Result<PathBuf> {
+    let path = match config_path {
+        Some(path) => path.clone(),
+        None => default_config_path()?,
+    };
+    if path.exists() && !force {
+        anyhow::bail!("{} already exists (use --force to overwrite)", path.display());
+    }
+
+    let config = Config {
+        sounds: EXAMPLE_SOUNDS
+            .iter()
+            .filter(|(_, file)| Path::new(file).exists())
+            .map(|(alias, file)| (alias.to_string(), file.to_string()))
+            .collect(),
+        ..Config::default()
+    };
+    if config.sounds.is_empty() {
+        eprintln!("Warning: No example sounds found; add your own aliases under 'sounds'.");
+    }
+
+    write_config_file(&path, &config, CONFIG_INIT_HEADER)?;
+    Ok(path)
+}
+
+// A sound argument with optional inline settings, e.g. `alert@50%,fo=0.2`
+#[derive(Debug, Clone, Default)]
+struct SoundSpec {
+    sound: String,
+    volume: Option<f32>,
+    fade: Option<f32>,
+    fade_out: Option<f32>,
+    fade_in: Option<f32>,
+}
+
+// Parse `<sound>[@<option>,...]` where options are `<volume>%`, `v=<volume>`, `f=<secs>`,
+// `fo=<secs>` and `fi=<secs>`. The split happens at the last `@` not written as `\@`;
+// an argument naming an existing file is always taken literally.
+fn parse_sound_spec(arg: &str) -> Result<SoundSpec> {
+    let literal = || SoundSpec {
+        sound: arg.replace("\\@", "@"),
+        ..Default::default()
+    };
+    if Path::new(arg).exists() {
+        return Ok(SoundSpec {
+            sound: arg.to_string(),
+            ..Default::default()
+        });
+    }
+
+    // Find the last '@' that is not escaped
+    let bytes = arg.as_bytes();
+    let Some(at) = (0..bytes.len()).rev().find(|&i| bytes[i] == b'@' && (i == 0 || bytes[i - 1] != b'\\')) else {
+        return Ok(literal());
+    };
+
+    let mut spec = SoundSpec {
+        sound: arg[..at].replace("\\@", "@"),
+        ..Default::default()
+    };
+    for option in arg[at + 1..].split(',').map(str::trim).filter(|o| !o.is_empty()) {
+        let parse = |value: &str| {
+            value
+                .trim_end_matches('%')
+                .parse::<f32>()
+                .map_err(|_| anyhow::anyhow!("Invalid value in inline option '{}' of '{}'", option, arg))
+        };
+        match option.split_once('=') {
+            Some(("v", value)) => spec.volume = Some(parse(value)?),
+            Some(("f", value)) => spec.fade = Some(parse(value)?),
+            Some(("fo", value)) => spec.fade_out = Some(parse(value)?),
+            Some(("fi", value)) => spec.fade_in = Some(parse(value)?),
+            None if option.ends_with('%') => spec.volume = Some(parse(option)?),
+            _ => anyhow::bail!(
+                "Unknown inline option '{}' in '{}' (use \\@ for a literal @)",
+                option,
+                arg
+            ),
+        }
+    }
+
+    Ok(spec)
+}
+
+fn active_port_override(config: &Config) -> Option<&PortOverride> {
+    // Only probe PulseAudio when the config actually has port overrides
+    if config.ports.is_empty() {
+        return None;
+    }
+
+    let port = get_pulseaudio_state(&[], &MirrorSinks::None).ok()?.active_port?;
+    config.ports.get(&port)
+}
+
+// Notification volume for a sink: its sink_volumes entry, else the global volume
+fn sink_volume(settings: &ServerSettings, sink: &str) -> f32 {
+    settings.sink_volumes.get(sink).copied().unwrap_or(settings.volume)
+}
+
+// Notification volume for the current default sink, for a client without the audio state
+fn active_sink_volume(settings: &ServerSettings) -> f32 {
+    // Only ask PulseAudio when the config actually has per-sink volumes
+    if settings.sink_volumes.is_empty() {
+        return settings.volume;
+    }
+
+    get_default_sink().map_or(settings.volume, |sink| sink_volume(settings, &sink))
+}
+
+// Minutes since local midnight, honouring TZ
+fn local_minute_of_day() -> Option<u32> {
+    unsafe {
+        let now = libc::time(std::ptr::null_mut());
+        let mut tm: libc::tm = std::mem::zeroed();
+        if libc::localtime_r(&now, &mut tm).is_null() {
+            return None;
+        }
+        Some((tm.tm_hour * 60 + tm.tm_min) as u32)
+    }
+}
+
+fn parse_clock_time(time: &str) -> Option<u32> {
+    let (hours, minutes) = time.trim().split_once(':')?;
+    let (hours, minutes) = (hours.parse::<u32>().ok()?, minutes.parse::<u32>().ok()?);
+    (hours < 24 && minutes < 60).then_some(hours * 60 + minutes)
+}
+
+// Parse "HH:MM-HH:MM" into minutes since midnight
+fn parse_time_range(range: &str) -> Result<(u32, u32)> {
+    let (start, end) = range
+        .split_once('-')
+        .context(format!("Invalid schedule time '{}', expected HH:MM-HH:MM", range))?;
+    match (parse_clock_time(start), parse_clock_time(end)) {
+        (Some(start), Some(end)) => Ok((start, end)),
+        _ => anyhow::bail!("Invalid schedule time '{}', expected HH:MM-HH:MM", range),
+    }
+}
+
+// The end is exclusive; a range ending before it starts wraps past midnight, and one that
+// ends where it starts covers the whole day
+fn time_in_range(minute: u32, start: u32, end: u32) -> bool {
+    match start.cmp(&end) {
+        std::cmp::Ordering::Less => start <= minute && minute < end,
+        std::cmp::Ordering::Greater => minute >= start || minute < end,
+        std::cmp::Ordering::Equal => true,
+    }
+}
+
+fn active_schedule_rule(schedule: &[ScheduleRule]) -> Option<&ScheduleRule> {
+    // Without a schedule there is no need to look at the clock
+    if schedule.is_empty() {
+        return None;
+    }
+
+    let minute = local_minute_of_day()?;
+    schedule.iter().find(|rule| match parse_time_range(&rule.time) {
+        Ok((start, end)) => time_in_range(minute, start, end),
+        Err(e) => {
+            eprintln!("Warning: Ignoring schedule rule: {}", e);
+            false
+        }
+    })
+}
+
+// The alias the active schedule rule plays instead of the requested sound, if it remaps it
+fn scheduled_sound<'a>(sound: &'a str, schedule: &'a [ScheduleRule]) -> &'a str {
+    active_schedule_rule(schedule)
+        .and_then(|rule| rule.sounds.get(sound))
+        .map_or(sound, String::as_str)
+}
+
+// Cap the volume by the active schedule rule; urgent sounds are never capped
+fn scheduled_volume(volume: f32, urgent: bool, schedule: &[ScheduleRule]) -> f32 {
+    if urgent {
+        return volume;
+    }
+    match active_schedule_rule(schedule).and_then(|rule| rule.volume) {
+        Some(cap) => volume.min(cap.clamp(0.0, 100.0)),
+        None => volume,
+    }
+}
+
+fn resolve_sound_path(sound: &str, config: &Config, port_override: Option<&PortOverride>) -> Result<PathBuf> {
+    // Aliases remapped for the active port win over the global ones
+    if let Some(path) = port_override.and_then(|o| o.sounds.get(sound)) {
+        return expand_path(path);
+    }
+
+    // Check if the sound is an alias in the config
+    if let Some(path) = config.sounds.get(sound) {
+        return expand_path(path);
+    }
+
+    if let Some(name) = sound.strip_prefix(BUILTIN_PREFIX) {
+        return builtin_tone_path(name);
+    }
+
+    // Otherwise, treat it as a direct path; one that exists is used verbatim, even with a $ in it
+    let path = PathBuf::from(sound);
+    if path.exists() {
+        return Ok(path);
+    }
+    let path = expand_path(sound)?;
+    if path.exists() {
+        return Ok(path);
+    }
+
+    // A bare event name (e.g. message-new-instant) is looked up in the freedesktop sound theme
+    if let Some(path) = theme_sound_file(sound) {
+        return Ok(path);
+    }
+
+    // Neither an alias nor an existing file: use the configured fallback, if any
+    match &config.fallback {
+        Some(fallback) => {
+            eprintln!("Warning: Unknown sound '{}', playing fallback '{}'", sound, fallback);
+            let fallback_path = match config.sounds.get(fallback) {
+                Some(path) => expand_path(path)?,
+                None => expand_path(fallback)?,
+            };
+            Ok(fallback_path)
+        }
+        None => anyhow::bail!("Unknown sound alias or missing file: {}", sound),
+    }
+}
+
+// Sounds named `builtin:<tone>` are generated rather than read from disk
+const BUILTIN_PREFIX: &str = "builtin:";
+const TONE_SAMPLE_RATE: u32 = 48000;
+
+// Beeps of a built-in tone: frequency (Hz), amplitude (0.0 - 1.0), length and the
+// silence after it (seconds)
+fn builtin_tone(name: &str) -> Option<&'static [(f32, f32, f32, f32)]> {
+    match name {
+        // A soft single beep
+        "normal" => Some(&[(660.0, 0.3, 0.18, 0.0)]),
+        // A sharp triple beep
+        "urgent" => Some(&[(1320.0, 0.6, 0.09, 0.06), (1320.0, 0.6, 0.09, 0.06), (1320.0, 0.6, 0.09, 0.0)]),
+        _ => None,
+    }
+}
+
+// Path of a built-in tone, written as a WAV file to the cache directory on first use
+fn builtin_tone_path(name: &str) -> Result<PathBuf> {
+    let beeps = builtin_tone(name).context(format!("Unknown built-in tone: {} (use normal or urgent)", name))?;
+    let cache_dir = dirs::cache_dir()
+        .context("Could not determine cache directory")?
+        .join("vh-notification-sound")
+        .join("builtin");
+    let path = cache_dir.join(format!("{}.wav", name));
+    if path.exists() {
+        return Ok(path);
+    }
+
+    std::fs::create_dir_all(&cache_dir)
+        .context(format!("Failed to create cache directory {}", cache_dir.display()))?;
+    // Write a temporary file so a failed run never leaves a truncated tone behind
+    let partial = path.with_extension("partial.wav");
+    std::fs::write(&partial, tone_wav(beeps)).context(format!("Failed to write {}", partial.display()))?;
+    std::fs::rename(&partial, &path)?;
+    Ok(path)
+}
+
+// 16-bit mono PCM WAV of sine beeps
+fn tone_wav(beeps: &[(f32, f32, f32, f32)]) -> Vec<u8> {
+    let mut samples: Vec<i16> = Vec::new();
+    for &(frequency, amplitude, length, gap) in beeps {
+        samples.extend(beep_samples(frequency, amplitude, length));
+        samples.extend(std::iter::repeat_n(0, (gap * TONE_SAMPLE_RATE as f32) as usize));
+    }
+    pcm_wav(1, &samples)
+}
+
+// One sine beep that ramps in and out over 5ms to avoid clicks
+fn beep_samples(frequency: f32, amplitude: f32, length: f32) -> Vec<i16> {
+    let rate = TONE_SAMPLE_RATE as f32;
+    let ramp = (0.005 * rate) as usize;
+    let count = (length * rate) as usize;
+    (0..count)
+        .map(|i| {
+            let envelope = (i.min(count - 1 - i) as f32 / ramp as f32).min(1.0);
+            let value = (2.0 * std::f32::consts::PI * frequency * i as f32 / rate).sin();
+            (value * amplitude * envelope * i16::MAX as f32) as i16
+        })
+        .collect()
+}
+
+// 16-bit PCM WAV at TONE_SAMPLE_RATE from interleaved samples
+fn pcm_wav(channels: u16, samples: &[i16]) -> Vec<u8> {
+    let block_align = channels as u32 * 2;
+    let data_len = (samples.len() * 2) as u32;
+    let mut wav = Vec::with_capacity(44 + data_len as usize);
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(36 + data_len).to_le_bytes());
+    wav.extend_from_slice(b"WAVEfmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes());
+    wav.extend_from_slice(&1u16.to_le_bytes()); // PCM
+    wav.extend_from_slice(&channels.to_le_bytes());
+    wav.extend_from_slice(&TONE_SAMPLE_RATE.to_le_bytes());
+    wav.extend_from_slice(&(TONE_SAMPLE_RATE * block_align).to_le_bytes());
+    wav.extend_from_slice(&(block_align as u16).to_le_bytes());
+    wav.extend_from_slice(&16u16.to_le_bytes());
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&data_len.to_le_bytes());
+    for sample in samples {
+        wav.extend_from_slice(&sample.to_le_bytes());
+    }
+    wav
+}
+
+// Length of each channel's beep in --sweep, and the pause before the next channel
+const SWEEP_BEEP: f32 = 0.4;
+const SWEEP_GAP: f32 = 0.3;
+
+// A WAV with one channel per sink channel, beeping on each in turn; the subwoofer gets a low
+// tone it can actually reproduce
+fn sweep_wav(channels: &[String]) -> Vec<u8> {
+    let step = ((SWEEP_BEEP + SWEEP_GAP) * TONE_SAMPLE_RATE as f32) as usize;
+    let mut samples = vec![0i16; step * channels.len() * channels.len()];
+    for (index, channel) in channels.iter().enumerate() {
+        let frequency = if channel == "lfe" { 60.0 } else { 660.0 };
+        for (i, sample) in beep_samples(frequency, 0.4, SWEEP_BEEP).into_iter().enumerate() {
+            samples[(index * step + i) * channels.len() + index] = sample;
+        }
+    }
+    pcm_wav(channels.len() as u16, &samples)
+}
+
+// Channel positions of a sink in its order, e.g. front-left,front-right,front-center,lfe
+fn channel_map(block: &[&str]) -> Vec<String> {
+    block
+        .iter()
+        .find_map(|line| line.trim().strip_prefix("Channel Map:"))
+        .map(|map| map.split(',').map(|channel| channel.trim().to_string()).filter(|channel| !channel.is_empty()).collect())
+        .unwrap_or_default()
+}
+
+// Beep on each channel of the default sink in turn, naming it as it plays. The player gets the
+// sink's channel map, so the file's channels go to the positions they were written for
+fn play_sweep(lock_path: &Path) -> Result<()> {
+    let sink = get_default_sink()?;
+    let sinks_output = pactl!("list", "sinks")?;
+    let channels = channel_map(&sink_block(&sinks_output, &sink));
+    if channels.is_empty() {
+        anyhow::bail!("Failed to get the channel map of sink {}", sink);
+    }
+
+    let path = lock_path.with_extension(format!("{}.sweep.wav", std::process::id()));
+    std::fs::write(&path, sweep_wav(&channels)).context(format!("Failed to write {}", path.display()))?;
+    let options = PlayerOptions {
+        sink: Some(&sink),
+        channel_map: Some(&channels),
+        ..PlayerOptions::default()
+    };
+    let played = spawn_player(None, &path.to_string_lossy(), false, &options).and_then(|child| {
+        println!("Sweeping {} ({} channels):", sink, channels.len());
+        for channel in &channels {
+            println!("  {}", channel);
+            thread::sleep(Duration::from_secs_f32(SWEEP_BEEP + SWEEP_GAP));
+        }
+        wait_player(child)
+    });
+    let _ = std::fs::remove_file(&path);
+    played
+}
+
+// Extensions of the files a sound directory is allowed to pick from
+const AUDIO_EXTENSIONS: &[&str] = &["oga", "ogg", "opus", "wav", "flac", "mp3"];
+
+fn audio_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files: Vec<PathBuf> = std::fs::read_dir(dir)
+        .context(format!("Failed to read sound directory {}", dir.display()))?
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.is_file()
+                && path
+                    .extension()
+                    .and_then(|ext| ext.to_str())
+                    .is_some_and(|ext| AUDIO_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()))
+        })
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+// A directory is only a valid sound if there is something in it to pick
+fn check_sound_dir(path: &Path) -> Result<()> {
+    if path.is_dir() && audio_files(path)?.is_empty() {
+        anyhow::bail!("No audio files ({}) found in {}", AUDIO_EXTENSIONS.join(", "), path.display());
+    }
+    Ok(())
+}
+
+// Pick a random audio file from a directory, avoiding `previous` when there is a choice
+fn pick_sound_from_dir(dir: &Path, previous: Option<&Path>) -> Result<PathBuf> {
+    let mut files = audio_files(dir)?;
+    if files.len() > 1 {
+        if let Some(previous) = previous {
+            files.retain(|file| file != previous);
+        }
+    }
+    if files.is_empty() {
+        anyhow::bail!("No audio files found in {}", dir.display());
+    }
+    let index = random_index(files.len());
+    Ok(files.swap_remove(index))
+}
+
+// Fresh random bits from the system's entropy pool, falling back to a time-seeded xorshift
+fn random_u64() -> u64 {
+    let mut bytes = [0u8; 8];
+    if File::open("/dev/urandom").and_then(|mut file| file.read_exact(&mut bytes)).is_ok() {
+        return u64::from_le_bytes(bytes);
+    }
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos() as u64).unwrap_or(0);
+    let mut x = nanos ^ ((std::process::id() as u64) << 32) | 1;
+    x ^= x << 13;
+    x ^= x >> 7;
+    x ^= x << 17;
+    x
+}
+
+fn random_index(len: usize) -> usize {
+    (random_u64() % len as u64) as usize
+}
+
+// Uniformly random offset in [-amount, amount]
+fn random_jitter(amount: f32) -> f32 {
+    let unit = (random_u64() >> 11) as f64 / (1u64 << 53) as f64;
+    ((unit * 2.0 - 1.0) * amount as f64) as f32
+}
+
+// The request's own notification volume, a share of the sink volume captured before ducking
+// (--volume-relative), or the server default. The urgent boost is added to a relative volume
+// here, since the client doesn't know the sink volume.
+fn request_volume(options: &RequestOptions, default_volume: f32, sink_volume: f32) -> f32 {
+    match options.relative_volume {
+        Some(percent) => {
+            let boost = if options.urgent { URGENT_VOLUME_BOOST } else { 0.0 };
+            (sink_volume * percent / 100.0 + boost).clamp(0.0, 100.0)
+        }
+        None => options.volume.unwrap_or(default_volume),
+    }
+}
+
+fn jittered_volume(volume: f32, jitter: f32) -> f32 {
+    if jitter <= 0.0 {
+        return volume;
+    }
+    (volume + random_jitter(jitter)).clamp(0.0, 100.0)
+}
+
+// Expand a leading ~ or ~user and $VAR or ${VAR} references; $$ stands for a literal $
+fn expand_path(path: &str) -> Result<PathBuf> {
+    let Some(after_tilde) = path.strip_prefix('~') else {
+        return Ok(PathBuf::from(expand_env_vars(path)?));
+    };
+
+    let (user, rest) = after_tilde.split_at(after_tilde.find('/').unwrap_or(after_tilde.len()));
+    let home_dir = if user.is_empty() {
+        dirs::home_dir().context("Could not determine home directory")?
+    } else {
+        user_home_dir(user).context(format!("Unknown user in path: ~{}", user))?
+    };
+
+    let rest = expand_env_vars(rest.trim_start_matches('/'))?;
+    if rest.is_empty() {
+        Ok(home_dir)
+    } else {
+        Ok(home_dir.join(rest))
+    }
+}
+
+fn expand_env_vars(value: &str) -> Result<String> {
+    let mut expanded = String::with_capacity(value.len());
+    let mut chars = value.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c != '$' {
+            expanded.push(c);
+            continue;
+        }
+
+        let mut name = String::new();
+        match chars.peek() {
+            Some('$') => {
+                chars.next();
+                expanded.push('$');
+                continue;
+            }
+            Some('{') => {
+                chars.next();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => name.push(c),
+                        None => anyhow::bail!("Unterminated ${{ in path: {}", value),
+                    }
+                }
+            }
+            _ => {
+                while let Some(&c) = chars.peek().filter(|c| c.is_ascii_alphanumeric() || **c == '_') {
+                    name.push(c);
+                    chars.next();
+                }
+            }
+        }
+
+        // A $ not followed by a variable name is kept as-is
+        if name.is_empty() {
+            expanded.push('$');
+            continue;
+        }
+        let var = std::env::var(&name).context(format!("Environment variable ${} used in path is not set", name))?;
+        expanded.push_str(&var);
+    }
+
+    Ok(expanded)
+}
+
+fn user_home_dir(user: &str) -> Option<PathBuf> {
+    let name = std::ffi::CString::new(user).ok()?;
+    // getpwnam returns static storage, so copy the directory out right away
+    let passwd = unsafe { libc::getpwnam(name.as_ptr()) };
+    if passwd.is_null() {
+        return None;
+    }
+    let dir = unsafe { std::ffi::CStr::from_ptr((*passwd).pw_dir) };
+    Some(PathBuf::from(dir.to_string_lossy().into_owned()))
+}
+
+// Tests hand external commands (pactl and the players) to a recording fake instead
+#[cfg(test)]
+type CommandHook = fn(&str, &[&str]) -> Result<String>;
+#[cfg(test)]
+static COMMAND_HOOK: Mutex<Option<CommandHook>> = Mutex::new(None);
+
+fn run_command(cmd: &str, args: &[&str]) -> Result<String> {
+    #[cfg(test)]
+    if let Some(hook) = *COMMAND_HOOK.lock().unwrap() {
+        return hook(cmd, args);
+    }
+
+    // Own process group so a Ctrl-C in the terminal can't kill a restore halfway
+    let output = Command::new(cmd)
+        .args(args)
+        .process_group(0)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .output()
+        .context(format!("Failed to execute command: {} {:?}", cmd, args))?;
+
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    } else {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        anyhow::bail!("Command failed: {} {:?}\nError: {}", cmd, args, stderr.trim())
+    }
+}
+
+// Format a volume percentage for pactl, keeping up to two decimals
+fn volume_arg(volume: f32) -> String {
+    format!("{}%", (volume * 100.0).round() / 100.0)
+}
+
+// The file to hand to the player: a copy resampled to the sink's rate when --resample
+// is on and the sound is a WAV at a different rate, otherwise the sound itself
+fn playable_sound(sound_path: &Path, settings: &ServerSettings, sink_rate: Option<u32>) -> PathBuf {
+    let Some(target_rate) = sink_rate.filter(|_| settings.resample) else {
+        return sound_path.to_path_buf();
+    };
+
+    match resampled_sound(sound_path, target_rate) {
+        Ok(Some(path)) => path,
+        Ok(None) => sound_path.to_path_buf(),
+        Err(e) => {
+            eprintln!("Warning: Failed to resample {}: {:#}", sound_path.display(), e);
+            sound_path.to_path_buf()
+        }
+    }
+}
+
+// Sample rate from a RIFF/WAVE header, None for anything else
+fn wav_sample_rate(path: &Path) -> Option<u32> {
+    let mut header = [0u8; 28];
+    File::open(path).ok()?.read_exact(&mut header).ok()?;
+    if &header[0..4] != b"RIFF" || &header[8..12] != b"WAVE" || &header[12..16] != b"fmt " {
+        return None;
+    }
+    Some(u32::from_le_bytes([header[24], header[25], header[26], header[27]]))
+}
+
+// Sound lengths by path and modification time; None where it couldn't be determined
+type DurationCache = HashMap<(PathBuf, SystemTime), Option<Duration>>;
+static SOUND_DURATIONS: Mutex<Option<DurationCache>> = Mutex::new(None);
+
+// Playing time of a sound file: parsed natively for WAV, otherwise asked of ffprobe or soxi
+// when installed. Cached, since probing runs a process
+fn sound_duration(path: &Path) -> Option<Duration> {
+    let modified = std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok()?;
+    let key = (path.to_path_buf(), modified);
+    if let Some(duration) = SOUND_DURATIONS.lock().unwrap().as_ref().and_then(|cache| cache.get(&key)) {
+        return *duration;
+    }
+
+    let duration = wav_duration(path).or_else(|| probe_duration(path));
+    SOUND_DURATIONS.lock().unwrap().get_or_insert_with(HashMap::new).insert(key, duration);
+    duration
+}
+
+// Length of a compressed sound as reported by ffprobe or soxi, whichever is installed
+fn probe_duration(path: &Path) -> Option<Duration> {
+    let path = path.to_string_lossy();
+    let probes: [(&str, &[&str]); 2] = [
+        ("ffprobe", &["-v", "error", "-show_entries", "format=duration", "-of", "default=noprint_wrappers=1:nokey=1"]),
+        ("soxi", &["-D"]),
+    ];
+    probes.iter().find_map(|(cmd, args)| {
+        let args: Vec<&str> = args.iter().copied().chain([path.as_ref()]).collect();
+        let seconds = run_command(cmd, &args).ok()?.trim().parse::<f64>().ok()?;
+        (seconds.is_finite() && seconds > 0.0).then(|| Duration::from_secs_f64(seconds))
+    })
+}
+
+// Playing time of a PCM WAV file, from its byte rate and data chunk size
+fn wav_duration(path: &Path) -> Option<Duration> {
+    let mut header = vec![0u8; 4096];
+    let len = File::open(path).ok()?.read(&mut header).ok()?;
+    header.truncate(len);
+    if header.len() < 36 || &header[0..4] != b"RIFF" || &header[8..12] != b"WAVE" {
+        return None;
+    }
+
+    // Walk the chunks after the RIFF header: fmt carries the byte rate, data the length
+    let mut byte_rate = None;
+    let mut offset = 12;
+    while offset + 8 <= header.len() {
+        let id = &header[offset..offset + 4];
+        let size = u32::from_le_bytes(header[offset + 4..offset + 8].try_into().ok()?);
+        if id == b"fmt " && offset + 20 <= header.len() {
+            byte_rate = Some(u32::from_le_bytes(header[offset + 16..offset + 20].try_into().ok()?));
+        } else if id == b"data" {
+            let byte_rate = byte_rate.filter(|rate| *rate > 0)?;
+            return Some(Duration::from_secs_f64(size as f64 / byte_rate as f64));
+        }
+        // Chunks are padded to an even size; a size running past usize ends the walk
+        offset = offset.checked_add(8 + size as usize + (size as usize & 1))?;
+    }
+    None
+}
+
+// Convert a WAV file to the target rate, reusing a cached conversion keyed by
+// path, modification time and rate. Returns None when no conversion is needed.
+fn resampled_sound(sound_path: &Path, target_rate: u32) -> Result<Option<PathBuf>> {
+    use std::hash::{Hash, Hasher};
+
+    let Some(rate) = wav_sample_rate(sound_path) else {
+        return Ok(None);
+    };
+    if rate == target_rate {
+        return Ok(None);
+    }
+
+    let modified = std::fs::metadata(sound_path)?.modified()?;
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    sound_path.hash(&mut hasher);
+    modified.hash(&mut hasher);
+    let cache_dir = dirs::cache_dir()
+        .context("Could not determine cache directory")?
+        .join("vh-notification-sound")
+        .join("resampled");
+    let cached = cache_dir.join(format!("{:016x}-{}.wav", hasher.finish(), target_rate));
+    if cached.exists() {
+        return Ok(Some(cached));
+    }
+
+    std::fs::create_dir_all(&cache_dir)
+        .context(format!("Failed to create cache directory {}", cache_dir.display()))?;
+    // Convert into a temporary file so a failed run never leaves a broken cache entry
+    let partial = cached.with_extension("partial.wav");
+    let input = sound_path.to_string_lossy();
+    let output = partial.to_string_lossy();
+    let rate_arg = target_rate.to_string();
+    run_command("sox", &[&input, "-r", &rate_arg, &output])
+        .or_else(|_| run_command("ffmpeg", &["-y", "-loglevel", "error", "-i", &input, "-ar", &rate_arg, &output]))
+        .context("Neither sox nor ffmpeg could convert the file")?;
+    std::fs::rename(&partial, &cached)?;
+
+    Ok(Some(cached))
+}
+
+// Larger pitch jitter turns a chime into a different sound
+const MAX_JITTER_PITCH: f32 = 25.0;
+
+// Start playing a queued sound: with the canberra backend, sounds from a theme are played by
+// event id; everything else goes to the player command or a default player, resampled and pitched if requested
+fn spawn_sound(settings: &ServerSettings, sound_path: &Path, sink_rate: Option<u32>, lock_path: &Path, mirror_sinks: &[String]) -> Result<Players> {
+    if settings.backend_player == BackendPlayer::Canberra {
+        if let Some(event) = theme_event_id(sound_path) {
+            if !mirror_sinks.is_empty() {
+                eprintln!("Warning: canberra-gtk-play only plays on the default sink");
+            }
+            return Ok(Players { main: spawn_canberra(&event)?, mirrors: Vec::new() });
+        }
+    }
+    let mut playable = playable_sound(sound_path, settings, sink_rate);
+    if settings.jitter_pitch > 0.0 {
+        let speed = 1.0 + random_jitter(settings.jitter_pitch) / 100.0;
+        match pitched_sound(&playable, speed, lock_path) {
+            Ok(path) => playable = path,
+            Err(e) => eprintln!("Warning: Failed to change the pitch of {}: {:#}", playable.display(), e),
+        }
+    }
+    // Every sink plays the same (resampled or pitched) copy, so they stay in step
+    let playable = playable.to_string_lossy();
+    let options = PlayerOptions {
+        properties: &settings.stream_properties,
+        ..PlayerOptions::default()
+    };
+    let main = spawn_player(settings.player_cmd.as_deref(), &playable, settings.foreground, &options)?;
+    let mut mirrors = Vec::new();
+    for sink in mirror_sinks {
+        let options = PlayerOptions { sink: Some(sink), ..options };
+        match spawn_player(settings.player_cmd.as_deref(), &playable, settings.foreground, &options) {
+            Ok(child) => mirrors.push((sink.clone(), child)),
+            Err(e) => eprintln!("Warning: Failed to play on {}: {:#}", sink, e),
+        }
+    }
+    Ok(Players { main, mirrors })
+}
+
+// Players started for one sound: one on the default sink and one per other sink played on
+struct Players {
+    main: Child,
+    mirrors: Vec<(String, Child)>,
+}
+
+impl Players {
+    fn pids(&self) -> Vec<u32> {
+        std::iter::once(&self.main).chain(self.mirrors.iter().map(|(_, child)| child)).map(Child::id).collect()
+    }
+
+    /// Wait for every player; the first failure, on the default sink before the others, is returned
+    fn wait(self) -> Result<()> {
+        let mirrors: Vec<Result<()>> = self
+            .mirrors
+            .into_iter()
+            .map(|(sink, child)| wait_player(child).context(format!("Failed to play on {}", sink)))
+            .collect();
+        wait_player(self.main)?;
+        mirrors.into_iter().collect()
+    }
+}
+
+// Each process keeps a single pitched copy next to its lock file, overwritten for every sound
+fn pitched_sound_path(lock_path: &Path) -> PathBuf {
+    lock_path.with_extension(format!("{}.pitched.wav", std::process::id()))
+}
+
+// Copy of the sound played `speed` times faster (and higher), like a turntable running fast.
+// ffmpeg needs the input rate for that, so without sox only WAV files can be pitched.
+fn pitched_sound(sound_path: &Path, speed: f32, lock_path: &Path) -> Result<PathBuf> {
+    let pitched = pitched_sound_path(lock_path);
+    // Convert into a temporary file so the player never sees a half-written one
+    let partial = pitched.with_extension("partial.wav");
+    let input = sound_path.to_string_lossy();
+    let output = partial.to_string_lossy();
+    let speed_arg = format!("{:.4}", speed);
+    run_command("sox", &[&input, &output, "speed", &speed_arg])
+        .or_else(|e| {
+            let rate = wav_sample_rate(sound_path).ok_or(e)?;
+            let filter = format!("asetrate={},aresample={}", (rate as f32 * speed).round() as u32, rate);
+            run_command("ffmpeg", &["-y", "-loglevel", "error", "-i", &input, "-af", &filter, &output])
+        })
+        .context("Neither sox nor ffmpeg could convert the file")?;
+    std::fs::rename(&partial, &pitched)?;
+    Ok(pitched)
+}
+
+// canberra-gtk-play blocks until the event has played; it runs in its own process group
+// like any other player, so an interrupt stops it the same way
+fn spawn_canberra(event: &str) -> Result<Child> {
+    Command::new("canberra-gtk-play")
+        .args(["-i", event, "-d", "vh-notification-sound"])
+        .process_group(0)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context(format!("Failed to start canberra-gtk-play for event {}", event))
+}
+
+// Theme sounds live in <data dir>/sounds/<theme>/<subdir>/<event id>.<ext>
+const THEME_SOUND_EXTENSIONS: [&str; 3] = ["oga", "ogg", "wav"];
+
+// File for an event name in the freedesktop theme, searching the XDG data directories in order
+fn theme_sound_file(name: &str) -> Option<PathBuf> {
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-') {
+        return None;
+    }
+
+    let data_home = std::env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| dirs::home_dir().map(|home| home.join(".local/share")));
+    let data_dirs = std::env::var("XDG_DATA_DIRS")
+        .ok()
+        .filter(|dirs| !dirs.is_empty())
+        .unwrap_or_else(|| "/usr/local/share:/usr/share".to_string());
+    data_home
+        .into_iter()
+        .chain(data_dirs.split(':').filter(|dir| !dir.is_empty()).map(PathBuf::from))
+        .map(|dir| dir.join("sounds").join("freedesktop").join("stereo"))
+        .flat_map(|dir| THEME_SOUND_EXTENSIONS.map(|ext| dir.join(format!("{}.{}", name, ext))))
+        .find(|path| path.is_file())
+}
+
+// Event id of a file inside a sound theme, e.g. message for .../sounds/freedesktop/stereo/message.oga
+fn theme_event_id(path: &Path) -> Option<String> {
+    let ext = path.extension()?.to_str()?;
+    if !THEME_SOUND_EXTENSIONS.contains(&ext) {
+        return None;
+    }
+    let sounds_dir = path.parent()?.parent()?.parent()?;
+    if sounds_dir.file_name()? != "sounds" {
+        return None;
+    }
+    path.file_stem()?.to_str().map(str::to_string)
+}
+
+// How a player is to play a sound besides the file itself
+#[derive(Debug, Clone, Copy, Default)]
+struct PlayerOptions<'a> {
+    // Sink to play on instead of the default one
+    sink: Option<&'a str>,
+    properties: &'a [(String, String)],
+    // Stream volume in PulseAudio's raw units (PA_VOLUME_NORM is 100%)
+    volume: Option<u32>,
+    // Positions of the file's channels, in PulseAudio's names (front-left, lfe, ...)
+    channel_map: Option<&'a [String]>,
+}
+
+// Start the player for a sound, on the default sink or the given one; a custom template runs
+// through `sh -c` with `{file}` replaced by the quoted sound path (appended when the template
+// has no `{file}`) and `{sink}` by the quoted sink name. PULSE_SINK carries the sink as well
+// for templates without `{sink}`. A template has no way to take a volume or channel map
+fn spawn_player(player_cmd: Option<&str>, sound_path: &str, log: bool, options: &PlayerOptions) -> Result<Child> {
+    let Some(template) = player_cmd else {
+        return spawn_default_player(sound_path, log, options);
+    };
+    if options.volume.is_some() || options.channel_map.is_some() {
+        anyhow::bail!("A player command can't set the stream volume or channel map; unset it to play with paplay, pw-play or pacat");
+    }
+    let (sink, properties) = (options.sink, options.properties);
+    let quoted = shell_quote(sound_path);
+    let template = if template.contains("{file}") {
+        template.to_string()
+    } else {
+        format!("{} {{file}}", template)
+    };
+    let script = expand_placeholders(&template, &[("{file}", &quoted), ("{sink}", &shell_quote(sink.unwrap_or("@DEFAULT_SINK@")))]);
+    let mut command = Command::new("sh");
+    command.arg("-c").arg(script);
+    if let Some(sink) = sink {
+        command.env("PULSE_SINK", sink);
+    }
+    // PulseAudio clients (and PipeWire's pulse layer) add these to their streams
+    if !properties.is_empty() {
+        let props: Vec<String> = properties.iter().map(|(key, value)| format!("{}=\"{}\"", key, value)).collect();
+        command.env("PULSE_PROP", props.join(" "));
+    }
+    start_player(command).context(format!("Failed to start player for {}", sound_path))
+}
+
+// Replace the placeholders of a template in one pass, so a value that happens to contain
+// another placeholder (a file named {sink}.wav) is left as it is
+fn expand_placeholders(template: &str, values: &[(&str, &str)]) -> String {
+    let mut expanded = String::with_capacity(template.len());
+    let mut rest = template;
+    while !rest.is_empty() {
+        match values.iter().find(|(placeholder, _)| rest.starts_with(placeholder)) {
+            Some((placeholder, value)) => {
+                expanded.push_str(value);
+                rest = &rest[placeholder.len()..];
+            }
+            None => {
+                let next = rest.chars().next().map_or(1, char::len_utf8);
+                expanded.push_str(&rest[..next]);
+                rest = &rest[next..];
+            }
+        }
+    }
+    expanded
+}
+
+// Own process group so interrupting also stops anything a template spawns
+fn start_player(mut command: Command) -> std::io::Result<Child> {
+    // A faked player is recorded, and `true` stands in for it so there is a child to wait for
+    #[cfg(test)]
+    if let Some(hook) = *COMMAND_HOOK.lock().unwrap() {
+        let program = command.get_program().to_string_lossy().to_string();
+        let args: Vec<String> = command.get_args().map(|arg| arg.to_string_lossy().to_string()).collect();
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        hook(&program, &args).map_err(|e| std::io::Error::other(e.to_string()))?;
+        command = Command::new("true");
+    }
+
+    command
+        .process_group(0)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+}
+
+// Players tried in order without a player command, with the arguments before the file and
+// the option choosing a sink; PipeWire-only systems may have pw-play but no paplay
+const DEFAULT_PLAYERS: [(&str, &[&str], Option<&str>); 4] = [
+    ("paplay", &[], Some("--device=")),
+    ("pw-play", &[], Some("--target=")),
+    ("pacat", &["--file-format"], Some("--device=")),
+    ("aplay", &["-q"], None),
+];
+
+// Index of the default player that started last, so later sounds skip the missing ones
+static DEFAULT_PLAYER: AtomicUsize = AtomicUsize::new(0);
+
+fn spawn_default_player(sound_path: &str, log: bool, options: &PlayerOptions) -> Result<Child> {
+    let first = DEFAULT_PLAYER.load(Ordering::Relaxed);
+    for (index, (player, args, sink_option)) in DEFAULT_PLAYERS.iter().enumerate().skip(first) {
+        let mut command = Command::new(player);
+        command.args(*args);
+        if let Some(sink) = options.sink {
+            let Some(sink_option) = sink_option else {
+                anyhow::bail!("{} can't play on a chosen sink", player);
+            };
+            command.arg(format!("{}{}", sink_option, sink));
+        }
+        command.args(stream_args(player, options)?);
+        command.args(property_args(player, options.properties));
+        command.arg(sound_path);
+        match start_player(command) {
+            Ok(child) => {
+                if index != first {
+                    DEFAULT_PLAYER.store(index, Ordering::Relaxed);
+                    if log {
+                        println!("player: {} ({} not found)", player, DEFAULT_PLAYERS[first].0);
+                    }
+                }
+                return Ok(child);
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e).context(format!("Failed to start {} for {}", player, sound_path)),
+        }
+    }
+    anyhow::bail!(
+        "No player found for {}: install paplay, pw-play, pacat or aplay, or set --player-cmd",
+        sound_path
+    )
+}
+
+// Stream properties in the form each default player takes them; aplay plays through ALSA
+// and has none
+fn property_args(player: &str, properties: &[(String, String)]) -> Vec<String> {
+    match player {
+        "paplay" | "pacat" => properties.iter().map(|(key, value)| format!("--property={}={}", key, value)).collect(),
+        "pw-play" if !properties.is_empty() => {
+            let object: serde_json::Map<String, serde_json::Value> =
+                properties.iter().map(|(key, value)| (key.clone(), value.clone().into())).collect();
+            vec![format!("--properties={}", serde_json::Value::Object(object))]
+        }
+        _ => Vec::new(),
+    }
+}
+
+// Stream volume and channel map in the form each default player takes them; aplay plays
+// through ALSA and takes neither
+fn stream_args(player: &str, options: &PlayerOptions) -> Result<Vec<String>> {
+    let mut args = Vec::new();
+    if let Some(volume) = options.volume {
+        match player {
+            "paplay" | "pacat" => args.push(format!("--volume={}", volume)),
+            // A linear factor, where PulseAudio's raw volume is on a cubic scale
+            "pw-play" => args.push(format!("--volume={:.4}", (volume as f32 / PA_VOLUME_NORM as f32).powi(3))),
+            _ => anyhow::bail!("{} can't set the stream volume", player),
+        }
+    }
+    if let Some(channels) = options.channel_map {
+        match player {
+            "paplay" | "pacat" => args.push(format!("--channel-map={}", channels.join(","))),
+            "pw-play" => {
+                let positions = channels
+                    .iter()
+                    .map(|channel| spa_channel(channel).context(format!("pw-play has no channel position for {}", channel)))
+                    .collect::<Result<Vec<_>>>()?;
+                args.push(format!("--channel-map={}", positions.join(",")));
+            }
+            _ => anyhow::bail!("{} can't set the channel map", player),
+        }
+    }
+    Ok(args)
+}
+
+// PipeWire's short name for a PulseAudio channel position, e.g. FL for front-left
+fn spa_channel(channel: &str) -> Option<String> {
+    let position = match channel {
+        "mono" => "MONO",
+        "front-left" | "left" => "FL",
+        "front-right" | "right" => "FR",
+        "front-center" | "center" => "FC",
+        "lfe" | "subwoofer" => "LFE",
+        "rear-left" => "RL",
+        "rear-right" => "RR",
+        "rear-center" => "RC",
+        "side-left" => "SL",
+        "side-right" => "SR",
+        "front-left-of-center" => "FLC",
+        "front-right-of-center" => "FRC",
+        "top-center" => "TC",
+        "top-front-left" => "TFL",
+        "top-front-right" => "TFR",
+        "top-front-center" => "TFC",
+        "top-rear-left" => "TRL",
+        "top-rear-right" => "TRR",
+        "top-rear-center" => "TRC",
+        _ => {
+            // aux0 ... aux31
+            let aux = channel.strip_prefix("aux")?.parse::<u8>().ok()?;
+            return Some(format!("AUX{}", aux));
+        }
+    };
+    Some(position.to_string())
+}
+
+fn wait_player(child: Child) -> Result<()> {
+    let output = child.wait_with_output().context("Failed to wait for player")?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        anyhow::bail!("Player exited with {}\nError: {}", output.status, stderr.trim())
+    }
+}
+
+// Run a hook command in the background with the sound in its environment. It is stopped
+// after HOOK_TIMEOUT, and a failure is only logged
+fn run_hook(stage: &'static str, command: &str, sound: &QueuedSound) -> Option<thread::JoinHandle<()>> {
+    let spawned = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .env("VH_NOTIFICATION_HOOK", stage)
+        .env("VH_NOTIFICATION_SOUND", &sound.path)
+        .env("VH_NOTIFICATION_ALIAS", sound.alias.as_deref().unwrap_or_default())
+        .process_group(0)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn();
+    let mut child = match spawned {
+        Ok(child) => child,
+        Err(e) => {
+            eprintln!("Warning: Failed to run {} hook: {}", stage, e);
+            return None;
+        }
+    };
+
+    Some(thread::spawn(move || {
+        let started = Instant::now();
+        loop {
+            match child.try_wait() {
+                Ok(Some(status)) => {
+                    if !status.success() {
+                        eprintln!("Warning: The {} hook failed with {}", stage, status);
+                    }
+                    return;
+                }
+                Ok(None) if started.elapsed() >= HOOK_TIMEOUT => {
+                    eprintln!("Warning: The {} hook did not finish within {}s, stopping it", stage, HOOK_TIMEOUT.as_secs());
+                    kill_player(child.id());
+                    let _ = child.wait();
+                    return;
+                }
+                Ok(None) => thread::sleep(Duration::from_millis(50)),
+                Err(e) => {
+                    eprintln!("Warning: Failed to wait for the {} hook: {}", stage, e);
+                    return;
+                }
+            }
+        }
+    }))
+}
+
+fn kill_player(pid: u32) {
+    unsafe {
+        libc::kill(-(pid as i32), libc::SIGTERM);
+    }
+}
+
+fn kill_players(pids: &[u32]) {
+    for pid in pids {
+        kill_player(*pid);
+    }
+}
+
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+fn get_default_sink() -> Result<String> {
+    pactl!("info")?
+        .lines()
+        .find(|line| line.contains("Default Sink"))
+        .map(|line| line.split(": ").nth(1).unwrap_or("").trim().to_string())
+        .context("Failed to get default sink")
+}
+
+// Role name that matches sink inputs without a media.role property
+const NO_ROLE: &str = "none";
+// Backlight pulses per flash, the dimmed level relative to the original and each phase's length
+const FLASH_PULSES: u32 = 2;
+const FLASH_DIM_LEVEL: f32 = 0.2;
+const FLASH_PHASE: Duration = Duration::from_millis(150);
+// Time a pre-play or post-play hook may run before it is stopped
+const HOOK_TIMEOUT: Duration = Duration::from_secs(5);
+// Time to connect to, send to and hear back from --webhook
+#[cfg(feature = "webhook")]
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(2);
+// PulseAudio's 100% volume on the raw scale used by paplay --volume
+const PA_VOLUME_NORM: u32 = 65536;
+const LOCK_FILE_NAME: &str = "vh-notification-sound.lock";
+// Largest remote request or reply accepted, in bytes
+const MAX_FRAME_SIZE: usize = 64 * 1024;
+// Percentage points added to the notification volume for --urgent
+const URGENT_VOLUME_BOOST: f32 = 20.0;
+// Share of a sound's length its fades may take with --adaptive-fade
+const ADAPTIVE_FADE_SHARE: f32 = 0.25;
+// Longest the background fades back in between an interrupted sound and the next one with
+// --fade-on-interrupt, before fading out again
+const INTERRUPT_FADE: Duration = Duration::from_millis(300);
+// Longest a --no-restore sound keeps the audio ducked when no next sound or --restore follows,
+// so a script that dies doesn't leave the music muted
+const NO_RESTORE_TIMEOUT: Duration = Duration::from_secs(30);
+// pactl lists whole percentages, so a volume we set reads back up to half a point off;
+// a sink further off than this was changed by someone else
+const USER_CHANGE_TOLERANCE: f32 = 1.0;
+// With --verify-restore, an input still muted after the restore is unmuted again this many
+// times, waiting in between for the sound server to settle
+const VERIFY_RESTORE_RETRIES: u32 = 3;
+const VERIFY_RESTORE_DELAY: Duration = Duration::from_millis(100);
+// Properties of the notification stream unless configured otherwise, so other tools can tell it
+// from regular audio; application.id marks it for the ducking of other instances to leave alone
+const APP_ID: &str = "vh-notification-sound";
+const DEFAULT_STREAM_PROPERTIES: [(&str, &str); 3] = [
+    ("media.role", "event"),
+    ("application.name", "Notification sound"),
+    ("application.id", APP_ID),
+];
+// Waiting requests the server keeps by default, and what replaces them with --overflow sound
+const DEFAULT_MAX_QUEUE: usize = 16;
+const DEFAULT_OVERFLOW_SOUND: &str = "builtin:urgent";
+// Longest --once-for a request may ask for (30 days); without --once-for a key lasts as long as the server
+const MAX_ONCE_FOR: f32 = 30.0 * 24.0 * 3600.0;
+// Longest --every interval (30 days)
+const MAX_EVERY: f32 = 30.0 * 24.0 * 3600.0;
+
+fn get_pulseaudio_state(duck_roles: &[String], mirror: &MirrorSinks) -> Result<PulseAudioState> {
+    // Get default sink
+    let mut default_sink = get_default_sink()?;
+
+    // A monitor default sink has no meaningful volume control: route to the sink
+    // it monitors when that sink exists, otherwise only mute inputs
+    let volume_output = pactl!("list", "sinks")?;
+    let mut volume_settable = true;
+    match volume_control_sink(&default_sink, &volume_output) {
+        Some(target) if target != default_sink => {
+            eprintln!("Warning: Default sink {} is a monitor, using {} for volume control", default_sink, target);
+            default_sink = target;
+        }
+        Some(_) => {}
+        None => {
+            eprintln!("Warning: Default sink {} is a monitor, skipping volume control", default_sink);
+            volume_settable = false;
+        }
+    }
+    let block = sink_block(&volume_output, &default_sink);
+
+    // Get current volume
+    let current_volume = channel_volumes(&block).first().copied();
+    let current_volume_str = match current_volume {
+        Some(volume) => volume,
+        None if !volume_settable => 100.0,
+        None => anyhow::bail!("Failed to get current volume"),
+    };
+
+    // Get the sample rate the default sink runs at, e.g. "s16le 2ch 48000Hz"
+    let sample_rate = block
+        .iter()
+        .find_map(|line| line.trim().strip_prefix("Sample Specification:"))
+        .and_then(|spec| spec.split_whitespace().find_map(|part| part.strip_suffix("Hz")))
+        .and_then(|rate| rate.parse::<u32>().ok());
+
+    // Get the active port of the default sink (absent for virtual sinks)
+    let active_port = block
+        .iter()
+        .find_map(|line| line.trim().strip_prefix("Active Port:"))
+        .map(|port| port.trim().to_string());
+
+    // Capture each sink the notification also plays on with its own volume to restore
+    let mirror_sinks = mirror_sink_names(&volume_output, mirror)
+        .into_iter()
+        .filter(|sink| *sink != default_sink)
+        .map(|sink| {
+            let volume = channel_volumes(&sink_block(&volume_output, &sink)).first().copied().unwrap_or(100.0);
+            (sink, volume)
+        })
+        .collect();
+
+    // Get unmuted sink inputs
+    let mut unmuted_inputs = Vec::new();
+    let names = sink_names(&volume_output);
+    let mut duck_sinks: Vec<(String, f32)> = Vec::new();
+
+    let inputs = get_sink_inputs()?;
+    for input in inputs
+        .into_iter()
+        // Another instance's notification is never ducked under ours
+        .filter(|input| !input.muted && input.app_id.as_deref() != Some(APP_ID) && should_duck_role(input.role.as_deref(), duck_roles))
+    {
+        // Fade the sink the input actually plays on
+        let sink = input
+            .sink
+            .as_ref()
+            .and_then(|index| names.get(index))
+            .cloned()
+            .unwrap_or_else(|| default_sink.clone());
+        if !duck_sinks.iter().any(|(name, _)| *name == sink) {
+            let volume = if sink == default_sink {
+                current_volume_str
+            } else {
+                channel_volumes(&sink_block(&volume_output, &sink)).first().copied().unwrap_or(100.0)
+            };
+            duck_sinks.push((sink, volume));
+        }
+        unmuted_inputs.push(input.id);
+    }
+
+    Ok(PulseAudioState {
+        default_sink,
+        current_volume: current_volume_str,
+        unmuted_inputs,
+        duck_sinks,
+        mirror_sinks,
+        active_port,
+        volume_settable,
+        sample_rate,
+    })
+}
+
+// A sink input (playing stream) as listed by `pactl list sink-inputs`
+#[derive(Debug, Clone)]
+struct SinkInput {
+    id: String,
+    // Treated as muted when the listing has no Mute line, so it is left alone
+    muted: bool,
+    // Per-channel volume percentages
+    volumes: Vec<f32>,
+    app_name: Option<String>,
+    role: Option<String>,
+    app_id: Option<String>,
+    // Index of the sink it plays on (the N of "Sink #N")
+    sink: Option<String>,
+}
+
+fn get_sink_inputs() -> Result<Vec<SinkInput>> {
+    Ok(parse_sink_inputs(&pactl!("list", "sink-inputs")?))
+}
+
+// Split the listing at its "Sink Input #N" headers in one pass, then read each block once
+fn parse_sink_inputs(details: &str) -> Vec<SinkInput> {
+    let mut blocks: Vec<(&str, Vec<&str>)> = Vec::new();
+    for line in details.lines() {
+        if let Some(id) = line.strip_prefix("Sink Input #") {
+            blocks.push((id.trim(), Vec::new()));
+        } else if let Some((_, block)) = blocks.last_mut() {
+            block.push(line);
+        }
+    }
+
+    blocks
+        .into_iter()
+        .map(|(id, block)| SinkInput {
+            id: id.to_string(),
+            muted: block_muted(&block).unwrap_or(true),
+            volumes: channel_volumes(&block),
+            app_name: block_property(&block, "application.name").map(str::to_string),
+            role: block_property(&block, "media.role").map(str::to_string),
+            app_id: block_property(&block, "application.id").map(str::to_string),
+            sink: block
+                .iter()
+                .find_map(|line| line.trim().strip_prefix("Sink:"))
+                .map(|index| index.trim().to_string()),
+        })
+        .collect()
+}
+
+// Everything --snapshot records; diffing the before and after files shows what
+// a notification failed to restore
+#[derive(Debug, Serialize)]
+struct AudioSnapshot {
+    pid: u32,
+    default_sink: String,
+    sink_mute: Option<bool>,
+    sink_volumes: Vec<f32>,
+    default_source: Option<String>,
+    source_mute: Option<bool>,
+    inputs: Vec<InputSnapshot>,
+}
+
+#[derive(Debug, Serialize)]
+struct InputSnapshot {
+    id: String,
+    application: Option<String>,
+    role: Option<String>,
+    mute: bool,
+    volumes: Vec<f32>,
+}
+
+fn get_audio_snapshot() -> Result<AudioSnapshot> {
+    let default_sink = get_default_sink()?;
+    let sinks = pactl!("list", "sinks")?;
+    let sink = sink_block(&sinks, &default_sink);
+    let default_source = get_default_source().ok();
+    let source_mute = default_source.as_deref().and_then(|source| source_muted(source).ok());
+
+    let inputs = get_sink_inputs()?
+        .into_iter()
+        .map(|input| InputSnapshot {
+            id: input.id,
+            application: input.app_name,
+            role: input.role,
+            mute: input.muted,
+            volumes: input.volumes,
+        })
+        .collect();
+
+    Ok(AudioSnapshot {
+        pid: std::process::id(),
+        sink_mute: block_muted(&sink),
+        sink_volumes: channel_volumes(&sink),
+        default_sink,
+        default_source,
+        source_mute,
+        inputs,
+    })
+}
+
+// Write <dir>/<stage>.json (stages: before, after); snapshots are debugging aids, so failures only warn
+fn write_snapshot(dir: &Path, stage: &str) {
+    let path = dir.join(format!("{}.json", stage));
+    let written = get_audio_snapshot().and_then(|snapshot| {
+        std::fs::create_dir_all(dir)?;
+        std::fs::write(&path, serde_json::to_string_pretty(&snapshot)? + "\n")?;
+        Ok(())
+    });
+    if let Err(e) = written {
+        eprintln!("Warning: Failed to write snapshot {}: {}", path.display(), e);
+    }
+}
+
+fn get_default_source() -> Result<String> {
+    pactl!("info")?
+        .lines()
+        .find(|line| line.contains("Default Source"))
+        .map(|line| line.split(": ").nth(1).unwrap_or("").trim().to_string())
+        .context("Failed to get default source")
+}
+
+// Whether a source is muted, from its block in `pactl list sources`
+fn source_muted(source: &str) -> Result<bool> {
+    let output = pactl!("list", "sources")?;
+    let name_line = format!("Name: {}", source);
+    Ok(output
+        .lines()
+        .skip_while(|line| line.trim() != name_line)
+        .take_while(|line| !line.starts_with("Source #"))
+        .any(|line| line.trim() == "Mute: yes"))
+}
+
+// A server that died with the audio ducked or the microphone muted left the original state in
+// its lock file; returns how many changes were undone
+fn restore_stale_audio(lock_info: &LockInfo) -> usize {
+    let mut restored = 0;
+    if let Some(audio) = &lock_info.ducked_audio {
+        let sinks = std::iter::once((&audio.default_sink, audio.volume))
+            .chain(audio.sink_volumes.iter().map(|(sink, volume)| (sink, *volume)));
+        for (sink, volume) in sinks {
+            match pactl!("set-sink-volume", sink, &volume_arg(volume)) {
+                Ok(_) => {
+                    eprintln!("Restored volume of {} to {:.0}% left ducked by PID {}", sink, volume, lock_info.pid);
+                    restored += 1;
+                }
+                Err(e) => eprintln!("Warning: Failed to restore volume of {}: {}", sink, e),
+            }
+        }
+
+        // Inputs that have closed since cannot be unmuted, and need not be
+        let unmuted = audio
+            .muted_inputs
+            .iter()
+            .filter(|input| pactl!("set-sink-input-mute", input, "0").is_ok())
+            .count();
+        if unmuted > 0 {
+            eprintln!("Unmuted {} input(s) left muted by PID {}", unmuted, lock_info.pid);
+            restored += unmuted;
+        }
+    }
+
+    if let Some(source) = &lock_info.muted_source {
+        match pactl!("set-source-mute", source, "0") {
+            Ok(_) => {
+                eprintln!("Unmuted source {} left muted by PID {}", source, lock_info.pid);
+                restored += 1;
+            }
+            Err(e) => eprintln!("Warning: Failed to unmute source {}: {}", source, e),
+        }
+    }
+    restored
+}
+
+// Force-stop a wedged server: SIGKILL skips its cleanup, so the audio state it saved in the
+// lock file is restored here before the lock file and control socket are removed
+fn kill_server(lock_path: &PathBuf) -> Result<()> {
+    let Some(pid) = lock_file_pid(lock_path) else {
+        println!("No notification server is running.");
+        return Ok(());
+    };
+
+    let alive = is_process_alive(pid);
+    if alive && !is_notification_server(pid) {
+        // The server is gone and its PID was reused; only its audio changes and files are left
+        println!("PID {} is no longer a notification server, cleaning up after it", pid);
+    } else if alive {
+        if unsafe { libc::kill(pid as libc::pid_t, libc::SIGKILL) } != 0 {
+            let error = std::io::Error::last_os_error();
+            anyhow::bail!("Failed to kill notification server (PID: {}): {}", pid, error);
+        }
+        let started = Instant::now();
+        while is_process_alive(pid) {
+            if started.elapsed() >= SERVER_EXIT_TIMEOUT {
+                anyhow::bail!("Notification server (PID: {}) is still running after SIGKILL.", pid);
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+        println!("Killed notification server (PID: {})", pid);
+    } else {
+        println!("Notification server (PID: {}) is not running, cleaning up after it", pid);
+    }
+
+    match read_lock_file(lock_path) {
+        Ok(lock_info) => {
+            if restore_stale_audio(&lock_info) == 0 {
+                println!("No audio changes to restore.");
+            }
+        }
+        Err(_) => eprintln!("Warning: No audio state in {}, nothing restored", lock_path.display()),
+    }
+
+    for path in [lock_path.clone(), lock_path.with_extension("sock")] {
+        match std::fs::remove_file(&path) {
+            Ok(()) => println!("Removed {}", path.display()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => eprintln!("Warning: Failed to remove {}: {}", path.display(), e),
+        }
+    }
+    Ok(())
+}
+
+// Sink names by index (the N of "Sink #N"), for resolving the Sink: line of sink inputs
+fn sink_names(sinks_output: &str) -> HashMap<String, String> {
+    let mut names = HashMap::new();
+    let mut index = None;
+    for line in sinks_output.lines() {
+        if let Some(number) = line.strip_prefix("Sink #") {
+            index = Some(number.trim().to_string());
+        } else if let Some(name) = line.trim().strip_prefix("Name:") {
+            if let Some(number) = index.take() {
+                names.insert(number, name.trim().to_string());
+            }
+        }
+    }
+    names
+}
+
+// Lines of `pactl list sinks` describing one sink, from its Name line to the next sink
+fn sink_block<'a>(sinks_output: &'a str, sink: &str) -> Vec<&'a str> {
+    let name_line = format!("Name: {}", sink);
+    sinks_output
+        .lines()
+        .skip_while(|line| line.trim() != name_line)
+        .take_while(|line| !line.starts_with("Sink #"))
+        .collect()
+}
+
+// Sinks to play on besides the default one; listed sinks that are missing or monitors are
+// skipped with a warning
+fn mirror_sink_names(sinks_output: &str, mirror: &MirrorSinks) -> Vec<String> {
+    match mirror {
+        MirrorSinks::None => Vec::new(),
+        MirrorSinks::All => {
+            let mut names: Vec<(u32, String)> = sink_names(sinks_output)
+                .into_iter()
+                .filter(|(_, name)| !is_monitor_sink(name, &sink_block(sinks_output, name)))
+                .map(|(index, name)| (index.parse().unwrap_or(u32::MAX), name))
+                .collect();
+            names.sort();
+            names.into_iter().map(|(_, name)| name).collect()
+        }
+        MirrorSinks::Listed(sinks) => sinks
+            .iter()
+            .filter(|sink| {
+                let block = sink_block(sinks_output, sink);
+                if block.is_empty() {
+                    eprintln!("Warning: Sink {} not found, not playing on it", sink);
+                    false
+                } else if is_monitor_sink(sink, &block) {
+                    eprintln!("Warning: Sink {} is a monitor, not playing on it", sink);
+                    false
+                } else {
+                    true
+                }
+            })
+            .cloned()
+            .collect(),
+    }
+}
+
+// The sink whose volume is ducked while default_sink is the default: the default sink itself, or
+// for a monitor the sink it monitors; None for a monitor of no listed sink
+fn volume_control_sink(default_sink: &str, sinks_output: &str) -> Option<String> {
+    let block = sink_block(sinks_output, default_sink);
+    if !is_monitor_sink(default_sink, &block) {
+        return Some(default_sink.to_string());
+    }
+    monitored_sink(default_sink, &block).filter(|target| !sink_block(sinks_output, target).is_empty())
+}
+
+// The default sink as the server ducks it (after the monitor reroute of get_pulseaudio_state),
+// to tell whether the user switched sinks since the audio was captured
+fn ducked_default_sink() -> Result<String> {
+    let default_sink = get_default_sink()?;
+    let sinks_output = pactl!("list", "sinks")?;
+    Ok(volume_control_sink(&default_sink, &sinks_output).unwrap_or(default_sink))
+}
+
+// Whether a sink is a monitor (its volume control does not affect what is heard)
+fn is_monitor_sink(sink: &str, block: &[&str]) -> bool {
+    sink.ends_with(".monitor")
+        || block.iter().any(|line| {
+            let line = line.trim();
+            line.strip_prefix("Monitor of Sink:")
+                .is_some_and(|target| target.trim() != "n/a")
+                || line == "device.class = \"monitor\""
+        })
+}
+
+// The sink a monitor sink is attached to, if it can be determined
+fn monitored_sink(sink: &str, block: &[&str]) -> Option<String> {
+    block
+        .iter()
+        .find_map(|line| {
+            let line = line.trim();
+            line.strip_prefix("Monitor of Sink:")
+                .map(str::trim)
+                .or_else(|| line.strip_prefix("device.master_device = ").map(|t| t.trim_matches('"')))
+        })
+        .filter(|target| *target != "n/a")
+        .map(str::to_string)
+        .or_else(|| sink.strip_suffix(".monitor").map(str::to_string))
+}
+
+// Value of a `key = "value"` property line in a pactl list block
+fn block_property<'a>(block: &[&'a str], key: &str) -> Option<&'a str> {
+    block.iter().find_map(|line| {
+        let value = line.trim().strip_prefix(key)?.trim_start().strip_prefix('=')?;
+        Some(value.trim().trim_matches('"'))
+    })
+}
+
+// Whether a pactl list block reports `Mute: yes`; None when it has no Mute line
+fn block_muted(block: &[&str]) -> Option<bool> {
+    block
+        .iter()
+        .find_map(|line| line.trim().strip_prefix("Mute:"))
+        .map(|mute| mute.trim() == "yes")
+}
+
+// Per-channel volume percentages from the Volume line of a pactl list block. The percentage
+// pactl shows is rounded to a whole number, so it is worked out from the raw value before it
+// ("front-left: 1966 /   3% / ...") when there is one; fades of a low volume need the fractions
+fn channel_volumes(block: &[&str]) -> Vec<f32> {
+    block
+        .iter()
+        .find(|line| line.trim().starts_with("Volume:"))
+        .map(|line| {
+            let parts: Vec<&str> = line.split_whitespace().collect();
+            parts
+                .iter()
+                .enumerate()
+                .filter_map(|(index, part)| {
+                    let percent = part.strip_suffix('%')?.parse::<f32>().ok()?;
+                    let raw = index
+                        .checked_sub(2)
+                        .filter(|_| parts[index - 1] == "/")
+                        .and_then(|raw_index| parts[raw_index].parse::<u32>().ok());
+                    // Two decimals, as volume_arg sends them back
+                    Some(raw.map_or(percent, |raw| (raw as f32 * 10000.0 / PA_VOLUME_NORM as f32).round() / 100.0))
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn should_duck_role(role: Option<&str>, duck_roles: &[String]) -> bool {
+    if duck_roles.is_empty() {
+        return true;
+    }
+    let role = role.unwrap_or(NO_ROLE);
+    duck_roles.iter().any(|r| r.eq_ignore_ascii_case(role))
+}
+
+fn set_lock_state(lock_path: &PathBuf, state: NotificationState) -> Result<()> {
+    if let Ok(mut lock_info) = read_lock_file(lock_path) {
+        lock_info.state = state;
+        update_lock_file(lock_path, &lock_info)?;
+    }
+    Ok(())
+}
+
+// Context for a failed lock file or control socket operation, naming the file involved;
+// main adds a --runtime-dir hint when the cause is a permission or disk space problem
+#[derive(Debug)]
+struct RuntimePathError {
+    operation: &'static str,
+    path: PathBuf,
+}
+
+impl std::fmt::Display for RuntimePathError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Failed to {} {}", self.operation, self.path.display())
+    }
+}
+
+fn runtime_path_error(operation: &'static str, path: &Path) -> RuntimePathError {
+    RuntimePathError { operation, path: path.to_path_buf() }
+}
+
+fn with_runtime_dir_hint(error: anyhow::Error) -> anyhow::Error {
+    let Some(failed) = error.downcast_ref::<RuntimePathError>() else {
+        return error;
+    };
+    let errno = error
+        .chain()
+        .find_map(|cause| cause.downcast_ref::<std::io::Error>())
+        .and_then(|e| e.raw_os_error());
+    let problem = match errno {
+        Some(libc::EACCES | libc::EPERM) => "is not writable",
+        Some(libc::EROFS) => "is on a read-only file system",
+        Some(libc::ENOSPC | libc::EDQUOT) => "is out of space",
+        _ => return error,
+    };
+    let dir = failed.path.parent().unwrap_or(Path::new("/")).display().to_string();
+    error.context(format!(
+        "The runtime directory {} {}; choose another one with --runtime-dir or VH_NOTIFICATION_RUNTIME_DIR",
+        dir, problem
+    ))
+}
+
+fn update_lock_file(lock_path: &PathBuf, lock_info: &LockInfo) -> Result<()> {
+    // Write a temporary file and rename it over the lock so readers never see a partial write;
+    // numbered, since the server's threads (or concurrent requests) may write at the same time
+    static WRITES: AtomicUsize = AtomicUsize::new(0);
+    let write = WRITES.fetch_add(1, Ordering::Relaxed);
+    let temp_path = lock_path.with_extension(format!("lock.{}.{}", std::process::id(), write));
+    let file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&temp_path)
+        .context(runtime_path_error("create lock file", &temp_path))?;
+
+    let written = serde_json::to_writer(&file, &lock_info)
+        .context(runtime_path_error("write lock file", &temp_path))
+        .and_then(|_| std::fs::rename(&temp_path, lock_path).context(runtime_path_error("replace lock file", lock_path)));
+    if written.is_err() {
+        let _ = std::fs::remove_file(&temp_path);
+    }
+
+    written
+}
+
+fn read_lock_file(lock_path: &PathBuf) -> Result<LockInfo> {
+    let file = OpenOptions::new()
+        .read(true)
+        .open(lock_path)
+        .context(format!("Failed to open lock file {}", lock_path.display()))?;
+
+    let lock_info: LockInfo = serde_json::from_reader(file)
+        .context(format!("Failed to parse lock file {}", lock_path.display()))?;
+
+    Ok(lock_info)
+}
+
+// Whether a process is still running. Uses /proc when it is mounted and falls back to
+// kill(pid, 0) otherwise, where EPERM means the process exists but belongs to someone else.
+fn is_process_alive(pid: u32) -> bool {
+    if Path::new("/proc/self").exists() {
+        return Path::new(&format!("/proc/{}", pid)).exists();
+    }
+
+    if unsafe { libc::kill(pid as libc::pid_t, 0) } == 0 {
+        return true;
+    }
+    std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+// Set the name shown by top and ps -o comm (at most 15 bytes); the command line is left alone
+// Name a detached server shows in top and ps -o comm
+const SERVER_PROCESS_TITLE: &str = "vh-notif-server";
+
+// Whether a live PID from the lock file is still a vh-notification-sound process, rather than
+// another one that got the PID of a dead server: by its executable, or by its name (our own,
+// cut to the kernel's 15 bytes, or the one a detached server takes). Without /proc there is
+// nothing to tell them apart by
+fn is_notification_server(pid: u32) -> bool {
+    if !Path::new("/proc/self").exists() {
+        return true;
+    }
+    let proc_dir = PathBuf::from(format!("/proc/{}", pid));
+    let exe = std::fs::read_link(proc_dir.join("exe")).ok();
+    if exe.is_some() && exe == std::env::current_exe().ok() {
+        return true;
+    }
+    let Ok(comm) = std::fs::read_to_string(proc_dir.join("comm")) else {
+        return false;
+    };
+    let comm = comm.trim_end_matches('\n');
+    let own_name = std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.file_name().map(|name| name.to_string_lossy().to_string()))
+        .unwrap_or_else(|| "vh-notification-sound".to_string());
+    comm == SERVER_PROCESS_TITLE || comm.as_bytes() == &own_name.as_bytes()[..own_name.len().min(15)]
+}
+
+fn set_process_title(title: &str) {
+    let name = &title.as_bytes()[..title.len().min(15)];
+    if let Ok(name) = std::ffi::CString::new(name) {
+        unsafe {
+            libc::prctl(libc::PR_SET_NAME, name.as_ptr() as libc::c_ulong, 0, 0, 0);
+        }
+    }
+}
+
+fn default_lock_path() -> PathBuf {
+    dirs::runtime_dir()
+        .unwrap_or_else(|| PathBuf::from("/tmp"))
+        .join(LOCK_FILE_NAME)
+}
+
+// Lock file in a --runtime-dir, creating the directory (private to the user) if needed
+fn runtime_dir_lock_path(dir: &Path) -> Result<PathBuf> {
+    use std::os::unix::fs::DirBuilderExt;
+
+    std::fs::DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(dir)
+        .context(format!("Failed to create runtime directory {}", dir.display()))?;
+    let c_dir = std::ffi::CString::new(dir.as_os_str().as_encoded_bytes())
+        .context(format!("Invalid runtime directory {}", dir.display()))?;
+    if unsafe { libc::access(c_dir.as_ptr(), libc::W_OK | libc::X_OK) } != 0 {
+        anyhow::bail!("Runtime directory {} is not writable", dir.display());
+    }
+    Ok(dir.join(LOCK_FILE_NAME))
+}
+
+// Every place a lock file may have been created: our own lock path, the default runtime
+// dir, the /tmp fallback and the runtime dirs of other users
+fn lock_path_candidates(lock_path: &Path) -> Vec<PathBuf> {
+    let mut candidates = vec![
+        lock_path.to_path_buf(),
+        default_lock_path(),
+        Path::new("/tmp").join(LOCK_FILE_NAME),
+    ];
+    if let Ok(entries) = std::fs::read_dir("/run/user") {
+        for entry in entries.flatten() {
+            candidates.push(entry.path().join(LOCK_FILE_NAME));
+        }
+    }
+    candidates.sort();
+    candidates.dedup();
+    candidates
+}
+
+// PID of the server that owns a lock file, in either the JSON or the legacy plain-text format
+fn lock_file_pid(lock_path: &PathBuf) -> Option<u32> {
+    match read_lock_file(lock_path) {
+        Ok(lock_info) => Some(lock_info.pid),
+        Err(_) => std::fs::read_to_string(lock_path).ok()?.trim().parse().ok(),
+    }
+}
+
+fn clean_stale_locks(own_lock_path: &Path) {
+    let mut removed = 0;
+    for lock_path in lock_path_candidates(own_lock_path) {
+        let socket_path = lock_path.with_extension("sock");
+        if !lock_path.exists() {
+            // A socket without its lock file can only be a leftover
+            if socket_path.exists() && std::fs::remove_file(&socket_path).is_ok() {
+                println!("Removed {}", socket_path.display());
+                removed += 1;
+            }
+            continue;
+        }
+
+        match lock_file_pid(&lock_path) {
+            Some(pid) if is_process_alive(pid) => {
+                println!("Kept {} (server PID {} is running)", lock_path.display(), pid);
+            }
+            Some(pid) => {
+                if let Ok(lock_info) = read_lock_file(&lock_path) {
+                    restore_stale_audio(&lock_info);
+                }
+                for path in [&lock_path, &socket_path] {
+                    if !path.exists() {
+                        continue;
+                    }
+                    match std::fs::remove_file(path) {
+                        Ok(()) => {
+                            println!("Removed {} (PID {} is not running)", path.display(), pid);
+                            removed += 1;
+                        }
+                        Err(e) => eprintln!("Warning: Failed to remove {}: {}", path.display(), e),
+                    }
+                }
+            }
+            None => eprintln!("Warning: Skipped {} (no PID found in it)", lock_path.display()),
+        }
+    }
+
+    if removed == 0 {
+        println!("No stale lock files found.");
+    }
+}
+
+fn acquire_lock(lock_path: &PathBuf, request: Option<&QueuedSound>) -> Result<Option<File>> {
+    let started = Instant::now();
+    loop {
+        if let Some(sent) = contact_running_server(lock_path, request, started)? {
+            return Ok(Some(sent));
+        }
+
+        // Another invocation may have created the lock since we found none; only one of
+        // us gets to start the server
+        let _guard = lock_guard(lock_path)?;
+        if lock_path.exists() {
+            continue;
+        }
+
+        // Create new lock file with initial state
+        let initial_lock_info = LockInfo {
+            pid: std::process::id(),
+            protocol: PROTOCOL_VERSION,
+            state: NotificationState::Idle,
+            new_request: None,
+            request_pid: None,
+            request_alias: None,
+            request_options: RequestOptions::default(),
+            request_once: None,
+            fade_progress: None,
+            last_pick: None,
+            muted_source: None,
+            ducked_audio: None,
+        };
+
+        update_lock_file(lock_path, &initial_lock_info)?;
+
+        // Return None to indicate we're starting a new process
+        return Ok(None);
+    }
+}
+
+// Hand the request to the server that owns the lock file, waiting out one that is exiting and
+// removing the lock of one that died; None once there is no lock file left
+fn contact_running_server(lock_path: &PathBuf, request: Option<&QueuedSound>, started: Instant) -> Result<Option<File>> {
+    // Check if lock file exists and is valid
+    while lock_path.exists() {
+        // Try to read the lock file as JSON
+        match read_lock_file(lock_path) {
+            Ok(lock_info) => {
+                // Check if the process in the lock file is still running
+                if is_process_alive(lock_info.pid) {
+                    if lock_info.protocol != PROTOCOL_VERSION {
+                        anyhow::bail!(incompatible_server(lock_info.pid, Some(lock_info.protocol)));
+                    }
+
+                    // A server that is shutting down takes no more requests: wait until it is
+                    // gone (or back to work, when a request raced its shutdown) and try again
+                    if lock_info.state == NotificationState::Exiting {
+                        wait_for_exiting_server(lock_info.pid, started)?;
+                        continue;
+                    }
+
+                    // A listening server can only be started when none is running
+                    let Some(request) = request else {
+                        anyhow::bail!("A notification server is already running (PID: {}).", lock_info.pid);
+                    };
+                    // The process is still running; prefer its control socket, where
+                    // requests from concurrent clients are all queued
+                    let play = ControlRequest::Play {
+                        sound: request.path.to_string_lossy().to_string(),
+                        alias: request.alias.clone(),
+                        options: request.options,
+                        pid: Some(std::process::id()),
+                        once: request.once.clone(),
+                    };
+                    let socket_path = lock_path.with_extension("sock");
+                    match exchange_control_request(&socket_path, &play) {
+                        Ok(response) if response.ok => {
+                            return Ok(Some(File::open(lock_path).context(runtime_path_error("open lock file", lock_path))?))
+                        }
+                        // Turned away by a server that has just started exiting
+                        Ok(_) => {
+                            wait_for_exiting_server(lock_info.pid, started)?;
+                            continue;
+                        }
+                        // A server that has only just claimed the lock opens its socket in a
+                        // moment; the lock file holds a single request, so a burst waits for it
+                        Err(_) if !socket_path.exists() && started.elapsed() < SERVER_START_TIMEOUT => {
+                            thread::sleep(Duration::from_millis(20));
+                            continue;
+                        }
+                        Err(_) => {}
+                    }
+
+                    // Fall back to writing the request into the lock file
+                    let mut updated_info = lock_info;
+                    updated_info.new_request = Some(request.path.to_string_lossy().to_string());
+                    updated_info.request_pid = Some(std::process::id());
+                    updated_info.request_alias = request.alias.clone();
+                    updated_info.request_options = request.options;
+                    updated_info.request_once = request.once.clone();
+                    update_lock_file(lock_path, &updated_info)?;
+                    return Ok(Some(File::open(lock_path).context(runtime_path_error("open lock file", lock_path))?));
+                } else {
+                    // Process is not running, remove stale lock
+                    restore_stale_audio(&lock_info);
+                    remove_stale_lock(lock_path, || lock_file_pid(lock_path) == Some(lock_info.pid))?;
+                }
+            }
+            Err(_) => {
+                // A lock file this version can't read: JSON from a server with another protocol
+                // version, or the plain PID written by versions before the JSON format
+                let contents = std::fs::read_to_string(lock_path)
+                    .context(runtime_path_error("read lock file", lock_path))?;
+                let owner = match serde_json::from_str::<ProtocolProbe>(&contents) {
+                    Ok(probe) => probe.pid.map(|pid| (pid, Some(probe.protocol))),
+                    Err(_) => contents.trim().parse::<u32>().ok().map(|pid| (pid, None)),
+                };
+                if let Some((pid, protocol)) = owner.filter(|(pid, _)| is_process_alive(*pid)) {
+                    anyhow::bail!(incompatible_server(pid, protocol));
+                }
+
+                // If the process is not running, remove the stale lock
+                remove_stale_lock(lock_path, || std::fs::read_to_string(lock_path).is_ok_and(|now| now == contents))?;
+            }
+        }
+    }
+    Ok(None)
+}
+
+// Serialises creating the lock file and removing a stale one between invocations. The guard
+// file is never removed: a new one would not exclude whoever holds the old one
+fn lock_guard(lock_path: &Path) -> Result<File> {
+    let guard_path = lock_path.with_extension("guard");
+    let file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&guard_path)
+        .context(runtime_path_error("create lock guard", &guard_path))?;
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } != 0 {
+        return Err(std::io::Error::last_os_error()).context(runtime_path_error("lock", &guard_path));
+    }
+    Ok(file)
+}
+
+// Remove a stale lock file, unless another invocation replaced it since we looked
+fn remove_stale_lock(lock_path: &PathBuf, still_stale: impl Fn() -> bool) -> Result<()> {
+    let _guard = lock_guard(lock_path)?;
+    if lock_path.exists() && still_stale() {
+        std::fs::remove_file(lock_path).context(runtime_path_error("remove stale lock file", lock_path))?;
+    }
+    Ok(())
+}
+
+// Wait (at most SERVER_START_TIMEOUT) for a forked server to write its PID into the lock we
+// claimed for it, so the lock never names an exited process in between
+fn wait_for_lock_handover(lock_path: &PathBuf, child: u32) {
+    let started = Instant::now();
+    while lock_file_pid(lock_path) != Some(child) {
+        if started.elapsed() >= SERVER_START_TIMEOUT || !is_process_alive(child) {
+            eprintln!("Warning: Detached server (PID: {}) has not taken over the lock file", child);
+            return;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+// Why a running server can't take our request; `protocol` is None for a plain-text lock file
+fn incompatible_server(pid: u32, protocol: Option<u32>) -> String {
+    let reason = match protocol {
+        Some(protocol) if protocol != PROTOCOL_VERSION => format!(
+            "uses protocol version {}, but this vh-notification-sound uses version {}",
+            protocol, PROTOCOL_VERSION
+        ),
+        Some(_) => "has a lock file this version cannot read".to_string(),
+        None => "is an older version with a plain-text lock file".to_string(),
+    };
+    format!(
+        "The running notification server (PID: {}) {}; wait for it to exit or stop it with `kill {}`.",
+        pid, reason, pid
+    )
+}
+
+// An exiting server only restores the audio (at most a fade-in) before it removes its lock
+const SERVER_EXIT_TIMEOUT: Duration = Duration::from_secs(5);
+// A server that just claimed the lock opens its control socket well within this
+const SERVER_START_TIMEOUT: Duration = Duration::from_secs(1);
+
+fn wait_for_exiting_server(pid: u32, started: Instant) -> Result<()> {
+    if started.elapsed() >= SERVER_EXIT_TIMEOUT {
+        anyhow::bail!("Notification server (PID: {}) is taking too long to exit.", pid);
+    }
+    thread::sleep(Duration::from_millis(20));
+    Ok(())
+}
+
+fn print_help_info() {
+    println!("VH Notification Sound");
+    println!("A simple application that plays notification sounds while temporarily fading out any currently playing audio.");
     println!("This application is designed specifically for Linux systems with PulseAudio.");
     println!();
     println!("GitHub Repository: https://github.com/vhqtvn/vh-notification-sound");
@@ -931,33 +6847,179 @@ fn print_help_info() {
     println!("  vh-notification-sound [OPTIONS] <SOUND>");
     println!();
     println!("ARGS:");
-    println!("  <SOUND>  Sound alias from config or path to audio file");
+    println!("  <SOUND>  Sound alias from config or path to an audio file (or a directory to pick");
+    println!("           one from at random) or a built-in tone (builtin:normal, builtin:urgent),");
+    println!("           optionally followed by");
+    println!("           inline settings: <SOUND>@<VOLUME>%,f=<SECS>,fo=<SECS>,fi=<SECS>");
     println!();
     println!("OPTIONS:");
     println!("  -f, --fade <SECONDS>       Fade duration for both in and out [default: 0.3]");
     println!("      --fade-out <SECONDS>   Fade out duration (overrides -f) [default: 0.3]");
     println!("      --fade-in <SECONDS>    Fade in duration (overrides -f) [default: 0.3]");
-    println!("  -v, --volume <PERCENT>     Output volume percentage (0-100) [default: 75]");
+    println!("      --ramp <NAME>          Shape fades with a ramp profile from the config");
+    println!("  -v, --volume <PERCENT>     Output volume percentage (0-100, e.g. 72.5) [default: 75]");
+    println!("      --volume-relative <PERCENT>  Output volume as a percentage of the current sink volume");
     println!("  -c, --config <FILE>        Path to config file");
+    println!("      --runtime-dir <DIR>    Keep the lock file and control socket here (one server per directory)");
     println!("  -l, --list-sounds          List available sound aliases from config");
+    println!("      --index <N>            Play the alias numbered N by --list-sounds");
+    println!("      --category <NAME>      Play the sound the config maps to this category");
     println!("  -h, --help-info            Show this help information");
     println!("  -d, --detach               Detach process and run in background");
+    println!("      --idle-timeout <SECONDS>   Keep the server running until idle for this long");
+    println!("      --persist-queue        Save the queue and replay it when the server restarts");
+    println!("      --player-cmd <TEMPLATE>    Play with a custom command, {{file}} is the sound path");
+    println!("      --backend-player <PLAYER>  Play theme sounds with paplay or canberra (by event id)");
+    println!("      --pre-hook <COMMAND>       Run a command right before each sound plays");
+    println!("      --post-hook <COMMAND>      Run a command once each sound has finished");
+    println!("      --webhook <URL>            POST every server event as JSON to an http:// URL");
+    println!("      --duck-roles <ROLES>       Only duck streams with these media roles (e.g. music,video,none)");
+    println!("      --stream-property <KEY=VALUE>  Set a property of the notification stream [default: media.role=event]");
+    println!("      --foreground           Stay attached and log state transitions and requests");
+    println!("      --resample             Convert WAV files to the sink's sample rate (needs sox or ffmpeg)");
+    println!("      --jitter-volume <PCT>  Vary each sound's volume randomly by up to this many points");
+    println!("      --jitter-pitch <PCT>   Vary each sound's speed and pitch randomly by up to this much");
+    println!("      --warmup               Wake a suspended sink before the fade-out to cut start latency");
+    println!("      --mute-mic             Also mute the default source (microphone) while the sound plays");
+    println!("      --fade-sink            Fade the sink to and from the notification volume, even with nothing playing");
+    println!("      --fade-on-interrupt  Briefly fade the background in and out between interrupted sounds");
+    println!("      --no-fade-on-interrupt  Keep the background ducked between interrupted sounds [default]");
+    println!("      --adaptive-fade        Shorten the fades of short sounds to a quarter of their length");
+    println!("      --sinks <SINKS>        Also play on these sinks (comma-separated), each with its volume restored");
+    println!("      --sink-all             Play on every sink at once, each with its volume restored");
+    println!("      --preserve-user-changes  Don't restore a volume changed by someone else during the sound");
+    println!("      --verify-restore       Retry unmuting streams still muted after the sound, then warn");
+    println!("      --time                 Print a timing breakdown of each phase to stderr");
+    println!("      --snapshot <DIR>       Write the audio state to DIR/before.json and DIR/after.json");
+    println!("      --no-duck              Play over other audio without muting, fading or volume changes");
+    println!("      --priority <LEVEL>     Request priority: low, normal or high [default: normal]");
+    println!("      --urgent               High priority, volume +{} points, not interruptible", URGENT_VOLUME_BOOST);
+    println!("                             (without a SOUND: the 'urgent' alias or builtin:urgent)");
+    println!("      --queue-policy <POLICY>    latest (newest only), fifo or lifo (play all) [default: latest]");
+    println!("      --max-queue <N>        Most requests the server keeps waiting, 0 for no limit [default: 16]");
+    println!("      --overflow <POLICY>    drop-oldest, drop-newest or sound (one overflow sound) [default: drop-oldest]");
+    println!("      --flash                Flash the screen backlight while the sound plays");
+    println!("      --silent-flash         Flash the screen backlight instead of playing the sound");
+    println!("      --until-ack            Repeat the sound, keeping other audio ducked, until --ack");
+    println!("      --no-restore           Leave other audio ducked after the sound for the next one (30s at most)");
+    println!("      --ttl <DURATION>       Drop the request if it hasn't started playing in time (e.g. 2s)");
+    println!("      --no-interrupt-current Let the playing sound finish on Ctrl-C (press again to stop it)");
+    println!("      --preview              Hear the sound at the notification volume without ducking");
+    println!("      --sweep                Beep on each channel of the default sink in turn");
+    println!("      --no-repeat            Don't pick the same file from a sound directory twice in a row");
+    println!("      --once <KEY>           Drop the request if the server already played one with this key");
+    println!("      --once-for <DURATION>  Forget the --once key after this time (e.g. 10m, at most 720h)");
+    println!("      --every <DURATION>     Play the sound again at this interval (e.g. 30m, at most 720h) until stopped");
+    println!("      --count <N>            Stop --every after N plays, the first one included");
+    println!("      --simple               Play once without the notification server (no queuing)");
+    println!("      --duck-while -- <COMMAND>  Duck the background while a command runs, without a sound");
+    println!("      --config-init          Write a starter config file (--force to overwrite)");
+    println!("      --no-config            Ignore all config files, use only options, environment and defaults");
+    println!("      --cwd-config           Also read ./vh-notification-sound.yml from the working directory");
+    println!("      --check-config <FILE>  Validate a config file and show where each alias resolves");
+    println!("      --allow-missing        Don't fail --check-config on sound files that don't exist");
+    println!("      --save-defaults        Save the resolved fade and volume settings to the config file");
+    println!("      --listen <ADDR>        Accept remote requests on TCP (a bare port binds 127.0.0.1)");
+    println!("      --remote <HOST:PORT>   Send the sound alias to a server started with --listen");
+    println!("      --token <SECRET>       Shared secret required by --listen and sent by --remote");
+    println!("      --clean                Remove lock files left behind by servers that are no longer running");
+    println!("      --status               Show the state of the running notification server");
+    println!("      --json                 Print --status or --list-queue output as JSON");
+    println!("      --list-queue           List the playing sound and queued requests with their ids");
+    println!("      --cancel <ID>          Drop a queued request, or stop it if it is playing");
+    println!("      --subscribe            Print notification server events as JSON lines");
+    println!("      --flush                Clear queued notifications, letting the current sound finish");
+    println!("      --flush-now            Clear queued notifications and stop the current sound");
+    println!("      --reload               Reload the running server's config (same as sending it SIGHUP)");
+    println!("      --ack                  Acknowledge the --until-ack alert of the running server");
+    println!("      --restore              Fade back in audio left ducked by --no-restore");
+    println!("      --kill                 SIGKILL a stuck server and restore the audio it left ducked");
+    println!("      --poll-interval <SECONDS>  Interval between checks for new requests [default: 0.05]");
+    println!("      --max-duration <SECONDS>   Stop watching for interruptions after this long [default: sound length, or 10]");
+    println!("      --pactl-rate <PER_SEC>     Maximum volume and mute changes per second [default: 100]");
     println!("      --help                 Show the automatically generated help message");
     println!();
     println!("ENVIRONMENT VARIABLES:");
     println!("  VH_NOTIFICATION_FADE       Default fade duration for both in and out");
     println!("  VH_NOTIFICATION_FADE_OUT   Default fade-out duration in seconds");
     println!("  VH_NOTIFICATION_FADE_IN    Default fade-in duration in seconds");
+    println!("  VH_NOTIFICATION_RAMP       Ramp profile used to shape fades");
     println!("  VH_NOTIFICATION_VOLUME     Default output volume percentage (0-100)");
+    println!("  VH_NOTIFICATION_VOLUME_RELATIVE Volume as a percentage of the current sink volume");
     println!("  VH_NOTIFICATION_CONFIG     Path to the configuration file");
+    println!("  VH_NOTIFICATION_CWD_CONFIG Also read ./vh-notification-sound.yml from the working directory");
+    println!("  VH_NOTIFICATION_RUNTIME_DIR Directory for the lock file and control socket");
     println!("  VH_NOTIFICATION_DETACH     Detach process and run in background");
+    println!("  VH_NOTIFICATION_POLL_INTERVAL  Interval between checks for new requests");
+    println!("  VH_NOTIFICATION_MAX_DURATION   Playback time after which interruptions are ignored");
+    println!("  VH_NOTIFICATION_PACTL_RATE     Maximum volume and mute changes per second");
+    println!("  VH_NOTIFICATION_IDLE_TIMEOUT   Idle seconds before a resident server exits");
+    println!("  VH_NOTIFICATION_PERSIST_QUEUE  Save the queue and replay it after a restart");
+    println!("  VH_NOTIFICATION_PLAYER_CMD     Command template used to play sounds");
+    println!("  VH_NOTIFICATION_BACKEND_PLAYER Player for theme sounds (paplay or canberra)");
+    println!("  VH_NOTIFICATION_PRE_HOOK       Command run right before each sound plays");
+    println!("  VH_NOTIFICATION_POST_HOOK      Command run once each sound has finished");
+    println!("  VH_NOTIFICATION_DUCK_ROLES     Comma-separated media roles to duck");
+    println!("  VH_NOTIFICATION_STREAM_PROPERTY  KEY=VALUE property of the notification stream");
+    println!("  VH_NOTIFICATION_NO_DUCK        Play without ducking other audio");
+    println!("  VH_NOTIFICATION_RESAMPLE       Convert WAV files to the sink's sample rate");
+    println!("  VH_NOTIFICATION_JITTER_VOLUME  Random volume variation per sound (percentage points)");
+    println!("  VH_NOTIFICATION_JITTER_PITCH   Random speed and pitch variation per sound (percent)");
+    println!("  VH_NOTIFICATION_WARMUP         Wake a suspended sink before the fade-out");
+    println!("  VH_NOTIFICATION_MUTE_MIC       Mute the default source while the sound plays");
+    println!("  VH_NOTIFICATION_FADE_SINK      Fade the sink volume even with nothing playing");
+    println!("  VH_NOTIFICATION_FADE_ON_INTERRUPT  Fade the background in and out between interrupted sounds");
+    println!("  VH_NOTIFICATION_ADAPTIVE_FADE  Cap fades at a quarter of the sound's length");
+    println!("  VH_NOTIFICATION_SINKS          Other sinks to play on (comma-separated)");
+    println!("  VH_NOTIFICATION_SINK_ALL       Play on every sink");
+    println!("  VH_NOTIFICATION_PRESERVE_USER_CHANGES  Leave volumes changed during the sound");
+    println!("  VH_NOTIFICATION_VERIFY_RESTORE  Retry unmuting streams still muted after the sound");
+    println!("  VH_NOTIFICATION_LISTEN         TCP address to accept remote requests on");
+    println!("  VH_NOTIFICATION_TOKEN          Shared secret for remote requests");
+    println!("  VH_NOTIFICATION_WEBHOOK        http:// URL every server event is posted to");
+    println!("  VH_NOTIFICATION_PRIORITY       Default request priority (low, normal, high)");
+    println!("  VH_NOTIFICATION_QUEUE_POLICY   Queue policy of the server: latest, fifo or lifo");
+    println!("  VH_NOTIFICATION_MAX_QUEUE      Most requests the server keeps waiting (0 for no limit)");
+    println!("  VH_NOTIFICATION_OVERFLOW       Full queue policy: drop-oldest, drop-newest or sound");
+    println!("  VH_NOTIFICATION_TTL            Default time a queued request stays relevant");
     println!();
     println!("EXAMPLES:");
     println!("  vh-notification-sound default");
     println!("  vh-notification-sound -f 0.5 default");
     println!("  vh-notification-sound --fade-out 0.5 --fade-in 0.2 --volume 80 /path/to/sound.mp3");
     println!("  vh-notification-sound -d default");
+    println!("  vh-notification-sound 'default@50%,fo=0.2'");
     println!("  vh-notification-sound -l");
+    println!("  vh-notification-sound --index 2");
+}
+
+fn print_status(lock_path: &PathBuf, socket_path: &Path, json: bool) {
+    let lock_info = read_lock_file(lock_path).ok().filter(|info| is_process_alive(info.pid));
+
+    if json {
+        println!("{}", serde_json::to_string(&lock_info).unwrap_or_else(|_| "null".to_string()));
+        return;
+    }
+
+    let Some(lock_info) = lock_info else {
+        println!("No notification server is running.");
+        return;
+    };
+    println!("PID: {}", lock_info.pid);
+    println!("State: {:?}", lock_info.state);
+    if let Some(progress) = lock_info.fade_progress {
+        println!("Fade: {:?} ({:.0}% background level)", progress.direction, progress.level * 100.0);
+    }
+    let playing = send_control_request(socket_path, &ControlRequest::List)
+        .ok()
+        .and_then(|response| response.queue)
+        .and_then(|queue| queue.playing);
+    if let Some(playing) = playing {
+        match playing.remaining {
+            Some(remaining) => println!("Playing: {} (~{:.1}s remaining)", playing.sound, remaining),
+            None => println!("Playing: {}", playing.sound),
+        }
+    }
 }
 
 fn print_sound_aliases(config: &Config) {
@@ -968,7 +7030,865 @@ fn print_sound_aliases(config: &Config) {
     }
 
     println!("Available sound aliases:");
-    for (alias, path) in &config.sounds {
-        println!("  {}: {}", alias, path);
+    for (number, (alias, path)) in sorted_aliases(config).into_iter().enumerate() {
+        println!("  {:>2}. {}: {}", number + 1, alias, path);
+    }
+
+    if !config.categories.is_empty() {
+        let mut categories: Vec<_> = config.categories.iter().collect();
+        categories.sort();
+        println!();
+        println!("Categories:");
+        for (category, alias) in categories {
+            println!("  {}: {}", category, alias);
+        }
+    }
+}
+
+// Aliases in the order --list-sounds numbers them
+fn sorted_aliases(config: &Config) -> Vec<(&String, &String)> {
+    let mut aliases: Vec<_> = config.sounds.iter().collect();
+    aliases.sort();
+    aliases
+}
+
+// Validate a single config file for --check-config: print each alias with the file it resolves
+// to and the defaults the file sets. Invalid settings always fail the check, sound files that
+// don't exist only without allow_missing
+fn check_config_file(path: &Path, allow_missing: bool) -> Result<()> {
+    let config = read_config_file(path).context(format!("Invalid config file {}", path.display()))?;
+    println!("Config: {}", path.display());
+
+    let mut missing = 0;
+    let mut errors = Vec::new();
+    let mut check_alias = |alias: &str, sound: &str, missing: &mut usize| match alias_file_status(sound) {
+        Ok((file, Ok(()))) => println!("  ok       {}: {}", alias, file.display()),
+        Ok((file, Err(e))) => {
+            *missing += 1;
+            println!("  missing  {}: {} ({})", alias, file.display(), e);
+        }
+        Err(e) => errors.push(format!("Sound alias {}: {:#}", alias, e)),
+    };
+
+    println!();
+    if config.sounds.is_empty() {
+        println!("No sound aliases defined.");
+    } else {
+        println!("Sound aliases:");
+        for (alias, sound) in sorted_aliases(&config) {
+            check_alias(alias, sound, &mut missing);
+        }
+    }
+
+    let mut ports: Vec<_> = config.ports.iter().collect();
+    ports.sort_by_key(|(port, _)| *port);
+    for (port, port_override) in ports {
+        let mut aliases: Vec<_> = port_override.sounds.iter().collect();
+        aliases.sort();
+        println!();
+        println!("Port {}:", port);
+        for (alias, sound) in aliases {
+            check_alias(alias, sound, &mut missing);
+        }
+    }
+
+    // Names that should lead to an alias or a playable file
+    let mut references: Vec<(String, &String)> = config
+        .categories
+        .iter()
+        .map(|(category, sound)| (format!("Category {}", category), sound))
+        .collect();
+    references.extend(config.fallback.iter().map(|sound| ("Fallback".to_string(), sound)));
+    for rule in &config.schedule {
+        references.extend(rule.sounds.values().map(|sound| (format!("Schedule rule {}", rule.time), sound)));
+    }
+    references.extend(config.preroll.iter().map(|(alias, sound)| (format!("Preroll {}", alias), sound)));
+    references.extend(config.overflow_sound.iter().map(|sound| ("Overflow sound".to_string(), sound)));
+    references.sort();
+    if !references.is_empty() {
+        println!();
+        println!("Sound references:");
+    }
+    for (name, sound) in references {
+        if sound_reference_resolves(&config, sound) {
+            println!("  ok       {}: {}", name, sound);
+        } else {
+            missing += 1;
+            println!("  missing  {}: {} (neither an alias nor a file)", name, sound);
+        }
+    }
+
+    let mut gates: Vec<_> = config.enabled_if.iter().collect();
+    gates.sort();
+    if !gates.is_empty() {
+        println!();
+        println!("Gates:");
+    }
+    for (name, var) in gates {
+        if !config.sounds.contains_key(name) && !config.categories.contains_key(name) {
+            errors.push(format!("Gate {}: neither an alias nor a category", name));
+            continue;
+        }
+        let state = if env_truthy(var) { "open" } else { "closed" };
+        println!("  {:<8} {}: {}", state, name, var);
+    }
+
+    for rule in &config.schedule {
+        if let Err(e) = parse_time_range(&rule.time) {
+            errors.push(format!("Schedule rule {}: {:#}", rule.time, e));
+        }
+    }
+    let mut properties: Vec<_> = config.stream_properties.iter().collect();
+    properties.sort();
+    for (key, value) in properties {
+        if let Err(e) = check_stream_property(key, value) {
+            errors.push(format!("Stream property {}: {}", key, e));
+        }
+    }
+    let mut ramps: Vec<_> = config.ramps.keys().chain(config.ramp.iter()).collect();
+    ramps.sort();
+    ramps.dedup();
+    for name in ramps {
+        if let Err(e) = resolve_ramp(&config, name) {
+            errors.push(format!("{:#}", e));
+        }
+    }
+
+    let (fade_out, fade_in) = (config.fade_out.unwrap_or(0.3), config.fade_in.unwrap_or(0.3));
+    let policy = config.queue_policy.unwrap_or_default();
+    let overflow = config.overflow.unwrap_or_default();
+    println!();
+    println!("Effective defaults:");
+    println!("  fade out:      {}s", fade_out);
+    println!("  fade in:       {}s", fade_in);
+    println!("  volume:        {}%", config.volume.unwrap_or(75.0).clamp(0.0, 100.0));
+    println!("  poll interval: {}s", config.poll_interval.unwrap_or(DEFAULT_POLL_INTERVAL));
+    match config.max_duration {
+        Some(max_duration) => println!("  max duration:  {}s", max_duration),
+        None => println!("  max duration:  sound length ({}s when unknown)", DEFAULT_MAX_DURATION),
+    }
+    match config.idle_timeout.filter(|timeout| *timeout > 0.0) {
+        Some(timeout) => println!("  idle timeout:  {}s", timeout),
+        None => println!("  idle timeout:  none"),
+    }
+    println!(
+        "  queue policy:  {}",
+        clap::ValueEnum::to_possible_value(&policy).map(|value| value.get_name().to_string()).unwrap_or_default()
+    );
+    match config.max_queue.unwrap_or(DEFAULT_MAX_QUEUE) {
+        0 => println!("  max queue:     none"),
+        max_queue => println!(
+            "  max queue:     {} (then {})",
+            max_queue,
+            clap::ValueEnum::to_possible_value(&overflow).map(|value| value.get_name().to_string()).unwrap_or_default()
+        ),
+    }
+    println!("  ramp:          {}", config.ramp.as_deref().unwrap_or("linear"));
+    let mut sink_volumes: Vec<_> = config.sink_volumes.iter().collect();
+    sink_volumes.sort_by_key(|(sink, _)| *sink);
+    for (sink, volume) in sink_volumes {
+        println!("  volume on {}: {}%", sink, volume.clamp(0.0, 100.0));
+    }
+
+    if !errors.is_empty() {
+        println!();
+        for error in &errors {
+            println!("Error: {}", error);
+        }
+        anyhow::bail!("{} has {} invalid setting(s)", path.display(), errors.len());
+    }
+    if missing > 0 {
+        if allow_missing {
+            eprintln!("Warning: {} sound(s) not found, ignored because of --allow-missing", missing);
+        } else {
+            anyhow::bail!("{} sound(s) in {} not found (use --allow-missing to ignore)", missing, path.display());
+        }
+    }
+    println!();
+    println!("Config OK");
+    Ok(())
+}
+
+// The absolute file an alias points to, and whether it can be played
+fn alias_file_status(sound: &str) -> Result<(PathBuf, Result<()>)> {
+    if let Some(name) = sound.strip_prefix(BUILTIN_PREFIX) {
+        builtin_tone(name).context(format!("Unknown built-in tone: {} (use normal or urgent)", name))?;
+        return Ok((PathBuf::from(sound), Ok(())));
+    }
+    let file = expand_path(sound)?;
+    let file = std::path::absolute(&file).unwrap_or(file);
+    let status = if file.exists() {
+        check_sound_dir(&file)
+    } else {
+        Err(anyhow::anyhow!("not found"))
+    };
+    Ok((file, status))
+}
+
+// Whether a category, fallback or schedule sound names an alias or something playable
+fn sound_reference_resolves(config: &Config, sound: &str) -> bool {
+    if config.sounds.contains_key(sound) {
+        return true;
+    }
+    if let Some(name) = sound.strip_prefix(BUILTIN_PREFIX) {
+        return builtin_tone(name).is_some();
+    }
+    Path::new(sound).exists()
+        || expand_path(sound).is_ok_and(|path| path.exists())
+        || theme_sound_file(sound).is_some()
+}
+
+// The variable gating an alias or category, when it is not truthy (1, true or yes) in our environment
+fn closed_gate<'a>(config: &'a Config, name: &str) -> Option<&'a str> {
+    let var = config.enabled_if.get(name)?;
+    (!env_truthy(var)).then_some(var.as_str())
+}
+
+fn env_truthy(var: &str) -> bool {
+    std::env::var(var).is_ok_and(|value| matches!(value.trim().to_ascii_lowercase().as_str(), "1" | "true" | "yes"))
+}
+
+fn alias_for_category(config: &Config, category: &str, urgent: bool) -> String {
+    if let Some(alias) = config.categories.get(category) {
+        return alias.clone();
+    }
+    match &config.fallback {
+        Some(fallback) => {
+            eprintln!("Warning: Unknown category '{}', playing fallback '{}'", category, fallback);
+            fallback.clone()
+        }
+        None => {
+            let tone = default_tone(config, urgent);
+            eprintln!("Warning: Unknown category '{}', playing '{}'", category, tone);
+            tone
+        }
+    }
+}
+
+// Sound for a request that names none: the `urgent` or `normal` alias when the config
+// defines it, the built-in tone otherwise
+fn default_tone(config: &Config, urgent: bool) -> String {
+    let name = if urgent { "urgent" } else { "normal" };
+    if config.sounds.contains_key(name) {
+        name.to_string()
+    } else {
+        format!("{}{}", BUILTIN_PREFIX, name)
+    }
+}
+
+// Alias shown as number `index` by --list-sounds
+fn alias_by_index(config: &Config, index: usize) -> Result<String> {
+    let aliases = sorted_aliases(config);
+    match index.checked_sub(1).and_then(|i| aliases.get(i)) {
+        Some((alias, _)) => Ok(alias.to_string()),
+        None if aliases.is_empty() => anyhow::bail!("No sound aliases found in config"),
+        None => anyhow::bail!("Sound index {} is out of range (1-{}, see --list-sounds)", index, aliases.len()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Every command the fake runner was asked to run, in order
+    static COMMANDS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+    // The command hook is global, so tests that fake commands take turns
+    static FAKE_RUNNER: Mutex<()> = Mutex::new(());
+
+    const FAKE_SINKS: &str = "Sink #0
+	State: RUNNING
+	Name: test_sink
+	Sample Specification: s16le 2ch 48000Hz
+	Mute: no
+	Volume: front-left: 45875 /  70% / -9.29 dB,   front-right: 45875 /  70% / -9.29 dB";
+
+    const FAKE_SINK_INPUTS: &str = "Sink Input #12
+	Sink: 0
+	Mute: no
+	Volume: front-left: 65536 / 100% / 0.00 dB,   front-right: 65536 / 100% / 0.00 dB
+	Properties:
+		application.name = \"Music\"
+		media.role = \"music\"";
+
+    // One sink at 70% with one unmuted music stream; state changes only get recorded
+    fn fake_command(cmd: &str, args: &[&str]) -> Result<String> {
+        COMMANDS.lock().unwrap().push(format!("{} {}", cmd, args.join(" ")));
+        Ok(match (cmd, args) {
+            ("pactl", ["info"]) => "Default Sink: test_sink\nDefault Source: test_source".to_string(),
+            ("pactl", ["list", "sinks"]) => FAKE_SINKS.to_string(),
+            ("pactl", ["list", "sink-inputs"]) => FAKE_SINK_INPUTS.to_string(),
+            _ => String::new(),
+        })
+    }
+
+    // As fake_command, but the music stream stays muted whatever pactl is told
+    fn stuck_mute_command(cmd: &str, args: &[&str]) -> Result<String> {
+        match (cmd, args) {
+            ("pactl", ["list", "sink-inputs"]) => {
+                COMMANDS.lock().unwrap().push("pactl list sink-inputs".to_string());
+                Ok(FAKE_SINK_INPUTS.replace("Mute: no", "Mute: yes"))
+            }
+            _ => fake_command(cmd, args),
+        }
+    }
+
+    // A resident server on the fake runner in its own runtime directory, up once its socket is
+    struct TestServer {
+        dir: PathBuf,
+        lock_path: PathBuf,
+        socket_path: PathBuf,
+        thread: thread::JoinHandle<Result<()>>,
+    }
+
+    fn start_server(name: &str, args: &[&str]) -> Result<TestServer> {
+        let dir = std::env::temp_dir().join(format!("vh-notification-sound-test-{}-{}", name, std::process::id()));
+        let lock_path = runtime_dir_lock_path(&dir)?;
+        let socket_path = lock_path.with_extension("sock");
+
+        let args = Args::parse_from(std::iter::once("vh-notification-sound").chain(args.iter().copied()));
+        let config = Config::default();
+        let inline = SoundSpec::default();
+        let settings = server_settings(&args, &inline, &config, None)?;
+        let source = SettingsSource { args, inline, config_file: None };
+        let thread = {
+            let (lock_path, socket_path) = (lock_path.clone(), socket_path.clone());
+            let running = Arc::new(AtomicBool::new(true));
+            thread::spawn(move || run_notification_server(None, settings, source, &config, running, lock_path, socket_path))
+        };
+
+        let started = Instant::now();
+        while !socket_path.exists() {
+            assert!(started.elapsed() < Duration::from_secs(5), "server did not open its control socket");
+            thread::sleep(Duration::from_millis(10));
+        }
+        Ok(TestServer {
+            dir,
+            lock_path,
+            socket_path,
+            thread,
+        })
+    }
+
+    // Wait for the server to go idle and exit, returning the commands it ran
+    fn stop_server(server: TestServer) -> Result<Vec<String>> {
+        let result = server.thread.join().expect("server thread panicked");
+        *COMMAND_HOOK.lock().unwrap() = None;
+        let _ = std::fs::remove_dir_all(&server.dir);
+        result?;
+        Ok(COMMANDS.lock().unwrap().clone())
+    }
+
+    #[test]
+    fn requests_from_clients_play_on_the_running_server() -> Result<()> {
+        let _runner = FAKE_RUNNER.lock().unwrap_or_else(|e| e.into_inner());
+        COMMANDS.lock().unwrap().clear();
+        *COMMAND_HOOK.lock().unwrap() = Some(fake_command);
+
+        // A resident server that plays every request in order and exits once idle
+        let server = start_server("clients", &["--fade", "0.05", "--idle-timeout", "0.5", "--queue-policy", "fifo"])?;
+        let (dir, lock_path, socket_path) = (server.dir.clone(), server.lock_path.clone(), server.socket_path.clone());
+
+        // Clients find the live server through the lock file and queue over its socket
+        let sounds: Vec<PathBuf> = ["one", "two", "three"].iter().map(|name| dir.join(format!("{}.wav", name))).collect();
+        for sound in &sounds {
+            let request = QueuedSound::new(sound.clone(), None, RequestOptions::default());
+            assert!(acquire_lock(&lock_path, Some(&request))?.is_some(), "request did not reach the server");
+        }
+
+        let commands = stop_server(server)?;
+
+        let plays: Vec<&String> = commands.iter().filter(|command| command.starts_with("paplay ")).collect();
+        // Each stream is marked as a notification (event) of ours for other tools and instances
+        let properties = "--property=media.role=event --property=application.name=Notification sound --property=application.id=vh-notification-sound";
+        let expected: Vec<String> = sounds.iter().map(|sound| format!("paplay {} {}", properties, sound.display())).collect();
+        assert_eq!(plays, expected.iter().collect::<Vec<_>>());
+
+        // The music was ducked before the first sound and restored after the last one
+        let position = |command: &str| commands.iter().position(|c| c == command);
+        let rposition = |command: &str| commands.iter().rposition(|c| c == command);
+        let first_play = position(&expected[0]).unwrap();
+        let last_play = rposition(&expected[2]).unwrap();
+        assert!(position("pactl set-sink-input-mute 12 1").is_some_and(|index| index < first_play));
+        assert!(rposition("pactl set-sink-input-mute 12 0").is_some_and(|index| index > last_play));
+        let last_volume = commands.iter().rfind(|command| command.starts_with("pactl set-sink-volume test_sink"));
+        assert_eq!(last_volume.map(String::as_str), Some("pactl set-sink-volume test_sink 70%"));
+
+        assert!(!lock_path.exists(), "lock file left behind");
+        assert!(!socket_path.exists(), "control socket left behind");
+        Ok(())
+    }
+
+    #[test]
+    fn silent_requests_leave_the_audio_alone() -> Result<()> {
+        let _runner = FAKE_RUNNER.lock().unwrap_or_else(|e| e.into_inner());
+        COMMANDS.lock().unwrap().clear();
+        *COMMAND_HOOK.lock().unwrap() = Some(fake_command);
+        let server = start_server("silent", &["--fade", "0.05", "--idle-timeout", "0.5"])?;
+
+        let options = RequestOptions {
+            silent: true,
+            ..RequestOptions::default()
+        };
+        let request = QueuedSound::new(server.dir.join("flash.wav"), None, options);
+        assert!(acquire_lock(&server.lock_path, Some(&request))?.is_some(), "request did not reach the server");
+
+        // Nothing is played, so nothing is ducked or restored either
+        let commands = stop_server(server)?;
+        let changes: Vec<&String> = commands
+            .iter()
+            .filter(|command| command.starts_with("paplay ") || command.starts_with("pactl set-"))
+            .collect();
+        assert!(changes.is_empty(), "silent request changed the audio: {:?}", changes);
+        Ok(())
+    }
+
+    #[test]
+    fn a_burst_of_clients_is_queued_in_full() -> Result<()> {
+        let _runner = FAKE_RUNNER.lock().unwrap_or_else(|e| e.into_inner());
+        COMMANDS.lock().unwrap().clear();
+        *COMMAND_HOOK.lock().unwrap() = Some(fake_command);
+        let server = start_server(
+            "burst",
+            &["--fade", "0", "--idle-timeout", "1", "--queue-policy", "fifo", "--max-queue", "0"],
+        )?;
+
+        // A hundred clients at once, as from a misbehaving script; none may be lost
+        let barrier = Arc::new(std::sync::Barrier::new(100));
+        let clients: Vec<_> = (0..100)
+            .map(|index| {
+                let (lock_path, sound, barrier) = (server.lock_path.clone(), server.dir.join(format!("{}.wav", index)), barrier.clone());
+                thread::spawn(move || {
+                    let request = QueuedSound::new(sound, None, RequestOptions::default());
+                    barrier.wait();
+                    acquire_lock(&lock_path, Some(&request)).map(|sent| sent.is_some())
+                })
+            })
+            .collect();
+        for client in clients {
+            assert!(client.join().expect("client thread panicked")?, "request did not reach the server");
+        }
+
+        let commands = stop_server(server)?;
+        let plays = commands.iter().filter(|command| command.starts_with("paplay ")).count();
+        assert_eq!(plays, 100);
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_once_for_is_refused_without_harming_the_server() -> Result<()> {
+        let _runner = FAKE_RUNNER.lock().unwrap_or_else(|e| e.into_inner());
+        COMMANDS.lock().unwrap().clear();
+        *COMMAND_HOOK.lock().unwrap() = Some(fake_command);
+        let server = start_server("once-for", &["--fade", "0", "--idle-timeout", "0.5"])?;
+        let play = |sound: &Path, once_for: f32| {
+            let options = RequestOptions {
+                once_for: Some(once_for),
+                ..RequestOptions::default()
+            };
+            let request = ControlRequest::Play {
+                sound: sound.to_string_lossy().to_string(),
+                alias: None,
+                options,
+                pid: None,
+                once: Some(format!("once-for-{}", once_for)),
+            };
+            exchange_control_request(&server.socket_path, &request)
+        };
+
+        // A negative time is refused instead of panicking with the --once keys locked
+        let refused = play(&server.dir.join("bad.wav"), -1.0)?;
+        assert!(!refused.ok, "negative once_for was accepted: {}", refused.message);
+
+        // and the server goes on queuing requests with --once keys
+        let good = server.dir.join("good.wav");
+        let accepted = play(&good, 60.0)?;
+        assert!(accepted.ok, "valid request refused: {}", accepted.message);
+
+        let commands = stop_server(server)?;
+        let plays: Vec<&String> = commands.iter().filter(|command| command.starts_with("paplay ")).collect();
+        assert_eq!(plays.len(), 1);
+        assert!(plays[0].ends_with(&*good.to_string_lossy()));
+        Ok(())
+    }
+
+    #[test]
+    fn duck_while_restores_after_the_command() -> Result<()> {
+        let _runner = FAKE_RUNNER.lock().unwrap_or_else(|e| e.into_inner());
+        COMMANDS.lock().unwrap().clear();
+        *COMMAND_HOOK.lock().unwrap() = Some(fake_command);
+        let dir = std::env::temp_dir().join(format!("vh-notification-sound-test-duck-while-{}", std::process::id()));
+        let lock_path = runtime_dir_lock_path(&dir)?;
+        acquire_lock(&lock_path, None)?;
+
+        let args = Args::parse_from(["vh-notification-sound", "--fade", "0.05", "--duck-while", "--", "say", "done"]);
+        let settings = server_settings(&args, &SoundSpec::default(), &Config::default(), None)?;
+        let running = Arc::new(AtomicBool::new(true));
+        let code = duck_while(&args.command, &settings, &running, &lock_path);
+        let lock_info = read_lock_file(&lock_path);
+        *COMMAND_HOOK.lock().unwrap() = None;
+        let _ = std::fs::remove_dir_all(&dir);
+        let commands = COMMANDS.lock().unwrap().clone();
+
+        assert_eq!(code?, 0);
+        // The music is muted before the command runs, at the sink's own volume, and unmuted after it
+        let position = |command: &str| commands.iter().position(|c| c == command);
+        let ran = position("say done").expect("command not run");
+        assert!(position("pactl set-sink-input-mute 12 1").is_some_and(|index| index < ran));
+        assert!(commands.iter().rposition(|c| c == "pactl set-sink-input-mute 12 0").is_some_and(|index| index > ran));
+        let volume = commands[..ran].iter().rfind(|command| command.starts_with("pactl set-sink-volume"));
+        assert_eq!(volume.map(String::as_str), Some("pactl set-sink-volume test_sink 70%"));
+
+        // Nothing is left recorded as ducked in the lock file
+        assert!(lock_info?.ducked_audio.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn unmutes_that_do_not_take_are_retried() -> Result<()> {
+        let _runner = FAKE_RUNNER.lock().unwrap_or_else(|e| e.into_inner());
+        let unmutes = || COMMANDS.lock().unwrap().iter().filter(|command| *command == "pactl set-sink-input-mute 12 0").count();
+        let restore = |hook: CommandHook| -> Result<AudioStateGuard> {
+            // The music stream plays unmuted until the notification
+            *COMMAND_HOOK.lock().unwrap() = Some(fake_command);
+            let mut guard = AudioStateGuard::new(get_pulseaudio_state(&[], &MirrorSinks::None)?);
+            COMMANDS.lock().unwrap().clear();
+            *COMMAND_HOOK.lock().unwrap() = Some(hook);
+            guard.verify_restore = true;
+            guard.set_needs_unmute_inputs();
+            guard.cleanup()?;
+            Ok(guard)
+        };
+
+        // An unmute that took is checked once and not repeated
+        restore(fake_command)?;
+        assert_eq!(unmutes(), 1);
+
+        // One that did not is retried, and the stream is then reported with its application
+        let guard = restore(stuck_mute_command)?;
+        assert_eq!(unmutes(), 1 + VERIFY_RESTORE_RETRIES as usize);
+        let still_muted = guard.still_muted_inputs()?;
+        *COMMAND_HOOK.lock().unwrap() = None;
+        assert_eq!(still_muted.len(), 1);
+        assert_eq!(still_muted[0].id, "12");
+        assert_eq!(still_muted[0].app_name.as_deref(), Some("Music"));
+        Ok(())
+    }
+
+    #[test]
+    fn an_interrupted_fade_in_is_reversed_from_where_it_stopped() -> Result<()> {
+        let _runner = FAKE_RUNNER.lock().unwrap_or_else(|e| e.into_inner());
+        *COMMAND_HOOK.lock().unwrap() = Some(fake_command);
+        let running = Arc::new(AtomicBool::new(true));
+        let mut guard = AudioStateGuard::new(get_pulseaudio_state(&[], &MirrorSinks::None)?);
+        guard.prepare_for_notification(0.05, true, true, 50.0, &running)?;
+
+        // A request arrives a few steps into the fade-in, and the next sound fades out again
+        COMMANDS.lock().unwrap().clear();
+        let checks = std::cell::Cell::new(0);
+        guard.restore_after_notification(0.05, true, &running, &|| {
+            checks.set(checks.get() + 1);
+            checks.get() > 4
+        })?;
+        let reached = guard.fade_state;
+        guard.prepare_for_notification(0.05, true, true, 50.0, &running)?;
+        guard.cleanup()?;
+        *COMMAND_HOOK.lock().unwrap() = None;
+        let commands = COMMANDS.lock().unwrap().clone();
+        assert!(0 < reached && reached < FADE_STEPS, "fade-in was not cut short: {}", reached);
+
+        // While the music is audible its volume only rises, then only falls
+        let unmuted = commands.iter().position(|c| c == "pactl set-sink-input-mute 12 0").expect("music not unmuted");
+        let muted = commands.iter().rposition(|c| c == "pactl set-sink-input-mute 12 1").expect("music not muted again");
+        let volumes: Vec<f32> = commands[..muted]
+            .iter()
+            .filter_map(|command| command.strip_prefix("pactl set-sink-volume test_sink "))
+            .map(|volume| volume.trim_end_matches('%').parse().unwrap())
+            .collect();
+        let before_unmute = commands[..unmuted].iter().filter(|c| c.starts_with("pactl set-sink-volume")).count();
+        let audible = &volumes[before_unmute.saturating_sub(1)..];
+        let peak = audible.iter().enumerate().max_by(|a, b| a.1.total_cmp(b.1)).map(|(index, _)| index).unwrap();
+        assert!(audible[..=peak].windows(2).all(|pair| pair[0] <= pair[1]), "fade-in not monotonic: {:?}", audible);
+        assert!(audible[peak..].windows(2).all(|pair| pair[0] >= pair[1]), "fade-out not monotonic: {:?}", audible);
+        assert!(audible[peak] < 70.0, "fade-in jumped to full volume: {:?}", audible);
+        Ok(())
+    }
+
+    #[test]
+    fn the_lock_of_a_dead_server_is_taken_over() -> Result<()> {
+        let _runner = FAKE_RUNNER.lock().unwrap_or_else(|e| e.into_inner());
+        COMMANDS.lock().unwrap().clear();
+        *COMMAND_HOOK.lock().unwrap() = Some(fake_command);
+        let dir = std::env::temp_dir().join(format!("vh-notification-sound-test-stale-{}", std::process::id()));
+        let lock_path = runtime_dir_lock_path(&dir)?;
+
+        // A server that died with the music ducked; its PID is reaped and gone
+        let mut dead = Command::new("true").spawn()?;
+        dead.wait()?;
+        let dead_pid = dead.id();
+        assert!(!is_process_alive(dead_pid));
+        assert!(is_process_alive(std::process::id()));
+        let lock = LockInfo {
+            pid: dead_pid,
+            protocol: PROTOCOL_VERSION,
+            state: NotificationState::Playing,
+            new_request: None,
+            request_pid: None,
+            request_alias: None,
+            request_options: RequestOptions::default(),
+            request_once: None,
+            fade_progress: None,
+            last_pick: None,
+            muted_source: None,
+            ducked_audio: Some(DuckedAudio {
+                default_sink: "test_sink".to_string(),
+                volume: 70.0,
+                sink_volumes: Vec::new(),
+                muted_inputs: vec!["12".to_string()],
+            }),
+        };
+        update_lock_file(&lock_path, &lock)?;
+
+        // The next invocation restores the audio and starts a server of its own
+        let request = QueuedSound::new(dir.join("next.wav"), None, RequestOptions::default());
+        let claimed = acquire_lock(&lock_path, Some(&request));
+        let owner = lock_file_pid(&lock_path);
+        *COMMAND_HOOK.lock().unwrap() = None;
+        let _ = std::fs::remove_dir_all(&dir);
+        let commands = COMMANDS.lock().unwrap().clone();
+
+        assert!(claimed?.is_none(), "request was handed to the dead server");
+        assert_eq!(owner, Some(std::process::id()));
+        assert!(commands.contains(&"pactl set-sink-volume test_sink 70%".to_string()));
+        assert!(commands.contains(&"pactl set-sink-input-mute 12 0".to_string()));
+        Ok(())
+    }
+
+    // A lock file as servers wrote it before the protocol was versioned
+    const UNVERSIONED_LOCK: &str = r#"{"pid":4242,"state":"Playing","new_request":"/tmp/next.wav","request_pid":77,"request_alias":"next","request_options":{"priority":"high","urgent":true,"flash":false,"silent":false,"no_repeat":false},"request_once":"backup","fade_progress":{"level":0.5,"direction":"out"},"last_pick":null,"muted_source":"alsa_input.mic"}"#;
+
+    #[test]
+    fn unversioned_lock_files_are_read_as_version_1() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("vh-notification-sound-test-unversioned-{}", std::process::id()));
+        let lock_path = runtime_dir_lock_path(&dir)?;
+        std::fs::write(&lock_path, UNVERSIONED_LOCK)?;
+        let lock_info = read_lock_file(&lock_path);
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let lock_info = lock_info?;
+        assert_eq!(lock_info.protocol, 1);
+        assert_eq!(lock_info.pid, 4242);
+        assert_eq!(lock_info.state, NotificationState::Playing);
+        assert_eq!(lock_info.new_request.as_deref(), Some("/tmp/next.wav"));
+        assert_eq!(lock_info.request_pid, Some(77));
+        assert_eq!(lock_info.request_alias.as_deref(), Some("next"));
+        assert_eq!(lock_info.request_options.priority, Priority::High);
+        assert!(lock_info.request_options.urgent);
+        assert_eq!(lock_info.request_once.as_deref(), Some("backup"));
+        assert_eq!(lock_info.muted_source.as_deref(), Some("alsa_input.mic"));
+        assert!(lock_info.ducked_audio.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn concurrent_starts_claim_the_lock_once() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("vh-notification-sound-test-claim-{}", std::process::id()));
+        let lock_path = runtime_dir_lock_path(&dir)?;
+
+        // A burst of invocations, as from a looping script running -d; the lock names this
+        // (live) test process, so the losers find a server whose socket never opens and
+        // fall back to the lock file
+        let barrier = Arc::new(std::sync::Barrier::new(8));
+        let starts: Vec<_> = (0..8)
+            .map(|index| {
+                let (lock_path, barrier) = (lock_path.clone(), barrier.clone());
+                thread::spawn(move || {
+                    let request = QueuedSound::new(PathBuf::from(format!("{}.wav", index)), None, RequestOptions::default());
+                    barrier.wait();
+                    acquire_lock(&lock_path, Some(&request)).map(|sent| sent.is_none())
+                })
+            })
+            .collect();
+        let mut servers = 0;
+        for start in starts {
+            if start.join().expect("start thread panicked")? {
+                servers += 1;
+            }
+        }
+        let _ = std::fs::remove_dir_all(&dir);
+
+        assert_eq!(servers, 1, "expected exactly one invocation to start a server");
+        Ok(())
+    }
+
+    // The default sink is the monitor of test_sink
+    fn monitor_default_command(cmd: &str, args: &[&str]) -> Result<String> {
+        match (cmd, args) {
+            ("pactl", ["info"]) => Ok("Default Sink: test_sink.monitor\nDefault Source: test_source".to_string()),
+            _ => fake_command(cmd, args),
+        }
+    }
+
+    // The default sink is the monitor of a sink that isn't listed
+    fn orphan_monitor_command(cmd: &str, args: &[&str]) -> Result<String> {
+        match (cmd, args) {
+            ("pactl", ["info"]) => Ok("Default Sink: gone_sink.monitor\nDefault Source: test_source".to_string()),
+            _ => fake_command(cmd, args),
+        }
+    }
+
+    #[test]
+    fn a_monitor_default_sink_ducks_the_sink_it_monitors() -> Result<()> {
+        let _runner = FAKE_RUNNER.lock().unwrap_or_else(|e| e.into_inner());
+        *COMMAND_HOOK.lock().unwrap() = Some(monitor_default_command);
+        let state = get_pulseaudio_state(&[], &MirrorSinks::None);
+        let ducked = ducked_default_sink();
+        *COMMAND_HOOK.lock().unwrap() = Some(orphan_monitor_command);
+        let orphan = get_pulseaudio_state(&[], &MirrorSinks::None);
+        *COMMAND_HOOK.lock().unwrap() = None;
+
+        // The volume of the monitored sink is controlled, and a later check sees no sink switch
+        let state = state?;
+        assert_eq!(state.default_sink, "test_sink");
+        assert!(state.volume_settable);
+        assert_eq!(state.current_volume, 70.0);
+        assert_eq!(ducked?, state.default_sink);
+
+        // Without the monitored sink only the inputs are muted
+        let orphan = orphan?;
+        assert_eq!(orphan.default_sink, "gone_sink.monitor");
+        assert!(!orphan.volume_settable);
+        Ok(())
+    }
+
+    #[test]
+    fn a_full_queue_drops_the_oldest_request_of_the_lowest_priority() {
+        let request = |name: &str, priority: Priority| {
+            let options = RequestOptions {
+                priority,
+                ..RequestOptions::default()
+            };
+            QueuedSound::new(PathBuf::from(name), None, options)
+        };
+        let limit = QueueLimit {
+            max: 3,
+            overflow: OverflowPolicy::DropOldest,
+            sound: None,
+        };
+        let mut queue: VecDeque<QueuedSound> =
+            [request("high", Priority::High), request("normal-1", Priority::Normal), request("normal-2", Priority::Normal)].into();
+        let names = |queue: &VecDeque<QueuedSound>| queue.iter().map(|queued| queued.path.display().to_string()).collect::<Vec<_>>();
+
+        // The older normal request makes room, not the older high one
+        let (enqueued, dropped) = overflow_queue(&mut queue, request("normal-3", Priority::Normal), &limit);
+        assert_eq!(enqueued, Enqueued::Queued);
+        assert_eq!(names(&dropped.into()), ["normal-1"]);
+        assert_eq!(names(&queue), ["high", "normal-2", "normal-3"]);
+
+        // A request below everything waiting is turned away
+        let (enqueued, dropped) = overflow_queue(&mut queue, request("low", Priority::Low), &limit);
+        assert_eq!(enqueued, Enqueued::Overflow);
+        assert!(dropped.is_empty());
+        assert_eq!(names(&queue), ["high", "normal-2", "normal-3"]);
+    }
+
+    #[test]
+    fn a_broken_config_is_not_reloaded() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("vh-notification-sound-test-reload-{}", std::process::id()));
+        std::fs::create_dir_all(&dir)?;
+        let path = dir.join("config.yml");
+        let source = SettingsSource {
+            args: Args::parse_from(["vh-notification-sound", "default"]),
+            inline: SoundSpec::default(),
+            config_file: Some(path.clone()),
+        };
+
+        std::fs::write(&path, "volume: 40\nsounds:\n  chime: /tmp/chime.wav\n")?;
+        let reloaded = reload_config(&source);
+        // Saved halfway through an edit, then emptied
+        std::fs::write(&path, "volume: 40\nsounds: [chime\n")?;
+        let broken = reload_config(&source);
+        std::fs::write(&path, "")?;
+        let empty = reload_config(&source);
+        let _ = std::fs::remove_dir_all(&dir);
+
+        assert_eq!(reloaded?.volume, Some(40.0));
+        assert!(broken.is_err(), "a config that doesn't parse was reloaded");
+        assert!(empty.is_err(), "an empty config was reloaded");
+        Ok(())
+    }
+
+    #[test]
+    fn stream_properties_that_would_break_pulse_prop_are_refused() {
+        // One property per flag, so a value keeps its commas
+        let args = Args::parse_from(["vh-notification-sound", "--stream-property", "application.name=Backup, nightly"]);
+        assert_eq!(args.stream_properties, vec![("application.name".to_string(), "Backup, nightly".to_string())]);
+
+        for property in ["application.name=Say \"hi\"", "application.name=C:\\sounds", "media role=event"] {
+            assert!(parse_stream_property(property).is_err(), "{} was accepted", property);
+        }
+    }
+
+    #[test]
+    fn each_player_gets_the_volume_and_channel_map_in_its_own_form() -> Result<()> {
+        let channels = ["front-left".to_string(), "front-right".to_string(), "lfe".to_string()];
+        let options = PlayerOptions {
+            volume: Some(PA_VOLUME_NORM / 2),
+            channel_map: Some(&channels),
+            ..PlayerOptions::default()
+        };
+        assert_eq!(stream_args("paplay", &options)?, ["--volume=32768", "--channel-map=front-left,front-right,lfe"]);
+        assert_eq!(stream_args("pw-play", &options)?, ["--volume=0.1250", "--channel-map=FL,FR,LFE"]);
+        assert!(stream_args("aplay", &options).is_err());
+        assert!(spawn_player(Some("mpv {file}"), "/tmp/sound.wav", false, &options).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn placeholders_in_the_sound_path_are_not_expanded() {
+        let values = [("{file}", "'/tmp/{sink}.wav'"), ("{sink}", "'speakers'")];
+        assert_eq!(expand_placeholders("mpv --audio-device=pulse/{sink} {file}", &values), "mpv --audio-device=pulse/'speakers' '/tmp/{sink}.wav'");
+    }
+
+    #[test]
+    fn a_truncated_wav_has_no_duration() -> Result<()> {
+        let path = std::env::temp_dir().join(format!("vh-notification-sound-test-truncated-{}.wav", std::process::id()));
+        // The file ends two bytes into the byte rate of the fmt chunk, which follows a junk chunk
+        let mut wav = b"RIFF\0\0\0\0WAVEjunk\x04\0\0\0\0\0\0\0fmt \x10\0\0\0".to_vec();
+        wav.extend_from_slice(&[1, 0, 2, 0, 0x80, 0xbb, 0, 0, 0x00, 0xee]);
+        std::fs::write(&path, &wav)?;
+        let duration = wav_duration(&path);
+        std::fs::remove_file(&path)?;
+        assert_eq!(duration, None);
+        Ok(())
+    }
+
+    #[test]
+    fn fades_of_a_low_volume_change_at_every_step() {
+        // pactl shows 1000 raw units as 2%; the fade works from the raw value
+        let block = ["\tVolume: front-left: 1000 /   2% / -109.06 dB,   front-right: 1000 /   2% / -109.06 dB"];
+        let volume = channel_volumes(&block)[0];
+        assert_eq!(volume, 1.53);
+
+        for ramp in [None, Some(vec![1.0, 0.6, 0.3, 0.1, 0.0])] {
+            let mut guard = AudioStateGuard::new(PulseAudioState {
+                default_sink: "quiet_sink".to_string(),
+                current_volume: volume,
+                unmuted_inputs: Vec::new(),
+                duck_sinks: vec![("quiet_sink".to_string(), volume)],
+                mirror_sinks: Vec::new(),
+                active_port: None,
+                volume_settable: true,
+                sample_rate: None,
+            });
+            guard.ramp = ramp;
+
+            // What set_fade_level hands to pactl, from full volume down to silence
+            let steps: Vec<f32> = (0..=FADE_STEPS)
+                .rev()
+                .map(|step| volume_arg(guard.step_volume(step)).trim_end_matches('%').parse().unwrap())
+                .collect();
+            assert!(steps.windows(2).all(|pair| pair[1] < pair[0]), "fade steps not strictly decreasing: {:?}", steps);
+            assert_eq!(steps.last(), Some(&0.0));
+        }
     }
 }